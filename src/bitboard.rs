@@ -2,6 +2,8 @@
 use std::ops::{BitOr, BitAnd, BitXor, Not};
 use std::fmt;
 
+use square::Square;
+
 const COL_A: u64 = 0x0101_0101_0101_0101;
 const COL_H: u64 = 0x8080_8080_8080_8080;
 
@@ -19,6 +21,10 @@ impl BitBoard {
     pub fn new(n: u64) -> Self {
         BitBoard(n)
     }
+    /// The raw 64 bits, with a1 as the lowest
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
     pub fn is_empty(&self) -> bool {
         self.0 == 0
     }
@@ -39,36 +45,34 @@ impl BitBoard {
         (64 - self.0.leading_zeros()) as usize
     }
 
+    /// Squares from this one to `other`, both included. Both must be single
+    /// squares on the same rank.
+    pub fn rank_span(&self, other: BitBoard) -> BitBoard {
+        let (lo, hi) = if self.0 < other.0 { (self.0, other.0) } else { (other.0, self.0) };
+        BitBoard((hi - lo) | hi)
+    }
+
     pub fn count_bits(&self) -> u32 {
         self.0.count_ones()
     }
 
-    pub fn to_str(&self) -> String {
+    /// The square of a single-square bitboard
+    pub fn to_square(&self) -> Square {
         debug_assert!(self.count_bits() == 1);
-        let lb = self.largest_bit() - 1;
-        let r = lb / 8;
-        let c = lb % 8;
-        debug_assert!(r <= 7);
-        debug_assert!(c <= 7);
+        Square::from_index(self.0.trailing_zeros() as usize)
+    }
+
+    /// Iterate over the squares in the bitboard, from a1 to h8
+    pub fn squares(&self) -> BitBoardSquares {
+        BitBoardSquares { bits_left: self.0 }
+    }
 
-        let mut s = String::new();
-        s.push(('a' as u8 + c as u8) as char);
-        s.push(('1' as u8 + r as u8) as char);
-        s
+    pub fn to_str(&self) -> String {
+        self.to_square().to_str()
     }
 
     pub fn from_str(s: &str) -> Option<BitBoard> {
-        if s.len() != 2 { return None; }
-        let mut it = s.trim().chars();
-        let cc = it.next().unwrap();
-        let rc = it.next().unwrap();
-        let c = cc as u8 - 'a' as u8;
-        let r = rc as u8 - '1' as u8;
-        if r <= 7 && c <= 7 {
-            Some(BitBoard(1 << (r * 8 + c)))
-        } else {
-            None
-        }
+        s.parse::<Square>().ok().map(|sq| sq.bb())
     }
 }
 
@@ -147,6 +151,20 @@ impl Iterator for BitBoardIntoIterator {
     }
 }
 
+pub struct BitBoardSquares {
+    bits_left: u64,
+}
+
+impl Iterator for BitBoardSquares {
+    type Item = Square;
+    fn next(&mut self) -> Option<Square> {
+        if self.bits_left == 0 { return None; }
+        let sq = Square::from_index(self.bits_left.trailing_zeros() as usize);
+        self.bits_left &= self.bits_left - 1;
+        Some(sq)
+    }
+}
+
 #[test]
 fn bit_ops() {
     let a = BitBoard(1);
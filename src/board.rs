@@ -1,15 +1,18 @@
 use std::fmt;
 
 use bitboard::BitBoard;
+use square::Square;
 use types::{Pc, Color};
 use types::Color::*;
-use types::PieceType::*;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Board {
     /// Array of bitboards, one for each piece. 2 colors * 6 pieces = 12 bitboards
     pub pieces: [BitBoard; 12],
 
+    /// Piece on each square, from a1 to h8. Kept in sync with the bitboards.
+    squares: [Option<Pc>; 64],
+
     /// White pieces
     pub whites: BitBoard,
 
@@ -25,72 +28,76 @@ impl Board {
     pub fn empty() -> Self {
         Board {
             pieces: [BitBoard::empty(); 12],
+            squares: [None; 64],
             whites: BitBoard::empty(),
             blacks: BitBoard::empty(),
             occupied: BitBoard::empty(),
         }
     }
 
-    /// Clear one square
-    pub fn clear(&mut self, sq: BitBoard) {
-        debug_assert!(sq.count_bits() == 1);
-
-        for bb in self.pieces.iter_mut() {
-            *bb = *bb & !sq;
-        }
-
-        self.recalc();
-    }
-
     /// Returns a new board with the same position
     pub fn duplicate(&self) -> Board {
         Board {
-            pieces: self.pieces.clone(),
+            pieces: self.pieces,
+            squares: self.squares,
             whites: self.whites,
             blacks: self.blacks,
             occupied: self.occupied
         }
     }
 
-    /// Put a piece on a square
-    pub fn set(&mut self, sq: BitBoard, p: Pc) {
-        debug_assert!(sq.count_bits() == 1);
+    /// Put a piece on an empty square
+    pub fn add(&mut self, sq: Square, p: Pc) {
+        debug_assert!(self.get(sq).is_none());
 
-        self.clear(sq);
+        let bb = sq.bb();
         let Pc(c, k) = p;
         let idx = c as usize + k as usize;
-        self.pieces[idx] = self.pieces[idx] | sq;
-        self.recalc();
+        self.pieces[idx] = self.pieces[idx] | bb;
+        match c {
+            White => { self.whites = self.whites | bb; },
+            Black => { self.blacks = self.blacks | bb; },
+        }
+        self.occupied = self.occupied | bb;
+        self.squares[sq.index()] = Some(p);
     }
 
-    /// Get the piece on a given square
-    pub fn get(&self, sq: BitBoard) -> Option<Pc> {
-        if sq.count_bits() != 1 {
-            println!("{}", sq);
+    /// Take the piece off a square, returning it
+    pub fn remove(&mut self, sq: Square) -> Option<Pc> {
+        let p = self.squares[sq.index()].take();
+        if let Some(Pc(c, k)) = p {
+            let bb = sq.bb();
+            let idx = c as usize + k as usize;
+            self.pieces[idx] = self.pieces[idx] & !bb;
+            match c {
+                White => { self.whites = self.whites & !bb; },
+                Black => { self.blacks = self.blacks & !bb; },
+            }
+            self.occupied = self.occupied & !bb;
         }
-        debug_assert!(sq.count_bits() == 1);
-
-        if (sq & self.occupied).is_empty() { return None; }
-
-        if (sq & self.pieces[0]).has_bits()  { return Some(Pc(White, Pawn)); }
-        if (sq & self.pieces[6]).has_bits()  { return Some(Pc(Black, Pawn)); }
-
-        if (sq & self.pieces[1]).has_bits()  { return Some(Pc(White, Knight)); }
-        if (sq & self.pieces[7]).has_bits()  { return Some(Pc(Black, Knight)); }
-
-        if (sq & self.pieces[2]).has_bits()  { return Some(Pc(White, Bishop)); }
-        if (sq & self.pieces[8]).has_bits()  { return Some(Pc(Black, Bishop)); }
+        p
+    }
 
-        if (sq & self.pieces[3]).has_bits()  { return Some(Pc(White, Rook)); }
-        if (sq & self.pieces[9]).has_bits()  { return Some(Pc(Black, Rook)); }
+    /// Move the piece on `from` to the empty square `to`
+    pub fn move_piece(&mut self, from: Square, to: Square) {
+        let p = self.remove(from).unwrap();
+        self.add(to, p);
+    }
 
-        if (sq & self.pieces[5]).has_bits()  { return Some(Pc(White, King)); }
-        if (sq & self.pieces[11]).has_bits() { return Some(Pc(Black, King)); }
+    /// Clear one square
+    pub fn clear(&mut self, sq: Square) {
+        self.remove(sq);
+    }
 
-        if (sq & self.pieces[4]).has_bits()  { return Some(Pc(White, Queen)); }
-        if (sq & self.pieces[10]).has_bits() { return Some(Pc(Black, Queen)); }
+    /// Put a piece on a square, replacing whatever was there
+    pub fn set(&mut self, sq: Square, p: Pc) {
+        self.remove(sq);
+        self.add(sq, p);
+    }
 
-        panic!("invalid board!");
+    /// Get the piece on a given square
+    pub fn get(&self, sq: Square) -> Option<Pc> {
+        self.squares[sq.index()]
     }
 
     /// Get a BitBoard the positions of all pieces of this type
@@ -114,14 +121,6 @@ impl Board {
             Black => self.whites
         }
     }
-
-    fn recalc(&mut self) {
-        self.whites = BitBoard::empty();
-        self.blacks = BitBoard::empty();
-        for i in 0..6  { self.whites = self.whites | self.pieces[i]; }
-        for i in 6..12 { self.blacks = self.blacks | self.pieces[i]; }
-        self.occupied = self.whites | self.blacks;
-    }
 }
 
 impl fmt::Display for Board {
@@ -130,8 +129,7 @@ impl fmt::Display for Board {
         for i in 0..8 {
             write!(f, "{} ", (7-i)+1);
             for j in 0..8 {
-                let sq = BitBoard::from_square(((7-i)*8 + j));
-                match self.get(sq) {
+                match self.get(Square::new(j, 7 - i)) {
                     None => write!(f, ". "),
                     Some(p) => p.fmt(f)
                 };
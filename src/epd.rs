@@ -0,0 +1,241 @@
+//! Extended Position Description records, as used by test suites
+
+use std::fmt;
+
+use types::Move;
+use pos::{Pos, FenError};
+
+/// A position and a list of operations, for example
+/// `2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";`
+#[derive(Debug)]
+pub struct Epd {
+    pub pos: Pos,
+    /// Opcodes with their operands in the order they appear. Quotes around
+    /// string operands are removed.
+    pub ops: Vec<(String, Vec<String>)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpdError {
+    /// The four position fields are invalid
+    Fen(FenError),
+    /// An operation is malformed
+    Operation(String),
+    /// A move operand is not legal in the position
+    Move(String),
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EpdError::Fen(ref e)       => write!(f, "{}", e),
+            EpdError::Operation(ref s) => write!(f, "invalid EPD operation: {}", s),
+            EpdError::Move(ref s)      => write!(f, "invalid move in EPD: '{}'", s),
+        }
+    }
+}
+
+impl Epd {
+    /// Parse an EPD line. The `hmvc` and `fmvn` opcodes, if present, set the
+    /// move counters of the position.
+    pub fn parse(s: &str) -> Result<Epd, EpdError> {
+        let s = s.trim();
+        let mut fields = Vec::new();
+        let mut rest = s;
+        for _ in 0..4 {
+            rest = rest.trim_start();
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            fields.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+
+        let mut pos = Pos::from_fen(&fields.join(" ")).map_err(EpdError::Fen)?;
+        let ops = parse_ops(rest)?;
+
+        let counter = |name: &str| -> Result<Option<usize>, EpdError> {
+            match ops.iter().find(|o| o.0 == name) {
+                None => Ok(None),
+                Some((_, args)) => match args.first().map(|a| a.parse::<usize>()) {
+                    Some(Ok(n)) => Ok(Some(n)),
+                    _ => Err(EpdError::Operation(format!("{} needs a number", name)))
+                }
+            }
+        };
+        if let Some(n) = counter("hmvc")? { pos.halfmoves = n; }
+        if let Some(n) = counter("fmvn")? { pos.moves = n; }
+
+        Ok(Epd { pos, ops })
+    }
+
+    /// Operands of the given opcode
+    pub fn op(&self, name: &str) -> Option<&[String]> {
+        self.ops.iter().find(|o| o.0 == name).map(|o| &o.1[..])
+    }
+
+    /// Set the operands of an opcode, adding it if it does not exist yet
+    pub fn set_op(&mut self, name: &str, operands: Vec<String>) {
+        match self.ops.iter_mut().find(|o| o.0 == name) {
+            Some(o) => { o.1 = operands; },
+            None => { self.ops.push((name.to_string(), operands)); }
+        }
+    }
+
+    /// The `id` of the record
+    pub fn id(&self) -> Option<&str> {
+        self.op("id").and_then(|a| a.first()).map(|s| &s[..])
+    }
+
+    /// Best moves, from the `bm` opcode
+    pub fn best_moves(&self) -> Result<Vec<Move>, EpdError> {
+        self.moves("bm")
+    }
+
+    /// Moves to avoid, from the `am` opcode
+    pub fn avoid_moves(&self) -> Result<Vec<Move>, EpdError> {
+        self.moves("am")
+    }
+
+    /// Resolve the operands of an opcode as moves. SAN is expected, but
+    /// coordinate notation is accepted too.
+    pub fn moves(&self, name: &str) -> Result<Vec<Move>, EpdError> {
+        let args = match self.op(name) {
+            None => { return Ok(Vec::new()); },
+            Some(a) => a
+        };
+        args.iter()
+            .map(|a| {
+                Move::from_san(&self.pos, a)
+                    .or_else(|| Move::from_str(&self.pos, a))
+                    .ok_or_else(|| EpdError::Move(a.clone()))
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Epd {
+    /// Writes the four position fields followed by the operations
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fen = self.pos.to_fen();
+        let fields: Vec<&str> = fen.split(' ').take(4).collect();
+        write!(f, "{}", fields.join(" "))?;
+        for (name, args) in self.ops.iter() {
+            write!(f, " {}", name)?;
+            for a in args.iter() {
+                if is_string_op(name) || a.is_empty() ||
+                   a.contains(|c: char| c.is_whitespace() || c == ';' || c == '"') {
+                    write!(f, " \"{}\"", a.replace('"', "'"))?;
+                } else {
+                    write!(f, " {}", a)?;
+                }
+            }
+            write!(f, ";")?;
+        }
+        Ok(())
+    }
+}
+
+/// Opcodes whose operands are always strings
+fn is_string_op(name: &str) -> bool {
+    let b = name.as_bytes();
+    name == "id" || (b.len() == 2 && (b[0] == b'c' || b[0] == b'v') && b[1].is_ascii_digit())
+}
+
+/// Parse `opcode operand...;` operations
+fn parse_ops(s: &str) -> Result<Vec<(String, Vec<String>)>, EpdError> {
+    let mut ops = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) { chars.next(); }
+        if chars.peek().is_none() { return Ok(ops); }
+
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == ';' { break; }
+            name.push(c);
+            chars.next();
+        }
+        if name.is_empty() {
+            return Err(EpdError::Operation("missing opcode".to_string()));
+        }
+
+        let mut args = Vec::new();
+        loop {
+            while chars.peek().is_some_and(|c| c.is_whitespace()) { chars.next(); }
+            match chars.next() {
+                None => {
+                    return Err(EpdError::Operation(format!("'{}' is not terminated by ';'", name)));
+                },
+                Some(';') => { break; },
+                Some('"') => {
+                    let mut arg = String::new();
+                    loop {
+                        match chars.next() {
+                            None => {
+                                return Err(EpdError::Operation(format!("unterminated string in '{}'", name)));
+                            },
+                            Some('"') => { break; },
+                            Some(c) => { arg.push(c); }
+                        }
+                    }
+                    args.push(arg);
+                },
+                Some(c) => {
+                    let mut arg = c.to_string();
+                    while let Some(&n) = chars.peek() {
+                        if n.is_whitespace() || n == ';' { break; }
+                        arg.push(n);
+                        chars.next();
+                    }
+                    args.push(arg);
+                }
+            }
+        }
+        ops.push((name, args));
+    }
+}
+
+#[test]
+fn parse_epd() {
+    let epd = Epd::parse("2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";").unwrap();
+    assert_eq!(epd.id(), Some("WAC.001"));
+    let bm = epd.best_moves().unwrap();
+    assert_eq!(bm.len(), 1);
+    assert_eq!(bm[0].to_str(), "g3g6");
+    assert_eq!(epd.avoid_moves().unwrap(), vec![]);
+
+    let epd = Epd::parse("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - \
+                          am Ba6 Qe2; bm Bb5 Bc4; ce 35; c0 \"two; bishops\"; hmvc 2; fmvn 3;").unwrap();
+    assert_eq!(epd.pos.to_fen(), "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+    let strs = |v: Vec<Move>| v.iter().map(|m| m.to_str()).collect::<Vec<String>>();
+    assert_eq!(strs(epd.best_moves().unwrap()), vec!["f1b5", "f1c4"]);
+    assert_eq!(strs(epd.avoid_moves().unwrap()), vec!["f1a6", "d1e2"]);
+    assert_eq!(epd.op("ce"), Some(&["35".to_string()][..]));
+    assert_eq!(epd.op("c0"), Some(&["two; bishops".to_string()][..]));
+    assert_eq!(epd.op("dm"), None);
+}
+
+#[test]
+fn epd_errors() {
+    assert!(matches!(Epd::parse("8/8/8 w - -"), Err(EpdError::Fen(_))));
+    assert!(matches!(Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - bm Ke2"), Err(EpdError::Operation(_))));
+    assert!(matches!(Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - id \"x;"), Err(EpdError::Operation(_))));
+
+    let epd = Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - bm Ke3;").unwrap();
+    assert_eq!(epd.best_moves(), Err(EpdError::Move("Ke3".to_string())));
+}
+
+#[test]
+fn write_epd() {
+    let line = "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";";
+    let mut epd = Epd::parse(line).unwrap();
+    assert_eq!(epd.to_string(), line);
+
+    epd.set_op("ce", vec!["+400".to_string()]);
+    epd.set_op("c0", vec!["mate threat".to_string()]);
+    epd.set_op("bm", vec!["Qg6".to_string(), "Qh4".to_string()]);
+    assert_eq!(epd.to_string(),
+               "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - \
+                bm Qg6 Qh4; id \"WAC.001\"; ce +400; c0 \"mate threat\";");
+    assert_eq!(Epd::parse(&epd.to_string()).unwrap().ops, epd.ops);
+}
@@ -1,7 +1,7 @@
 #![allow(dead_code)]
 
 use types::PieceType::*;
-use types::Pc;
+use types::{Pc, PieceType};
 use pos::Pos;
 
 use types::Color::*;
@@ -97,6 +97,11 @@ const BASE_VALUES: [i64; 6] = [
     1_000_000
 ];
 
+/// Material value of a piece type, without regard to its square
+pub fn piece_value(t: PieceType) -> i64 {
+    BASE_VALUES[t as usize]
+}
+
 fn base_value(p: Pc) -> i64 {
     match p {
         Pc(White, t) => BASE_VALUES[t as usize],
@@ -104,14 +109,39 @@ fn base_value(p: Pc) -> i64 {
     }
 }
 
+/// Score for giving mate. Mate in n plies scores `MATE - n`, and being
+/// mated in n plies `n - MATE`.
+pub const MATE: i64 = 100_000;
+/// Deeper than any search goes, so scores within this of `MATE` are mates
+pub const MAX_PLY: usize = 128;
+/// Beyond any score, for the initial search window
+pub const INFINITY: i64 = MATE + 1;
+
+/// Plies to mate if the score is a mate score, negative when it is the
+/// side the score belongs to that gets mated
+pub fn mate_in(score: i64) -> Option<i64> {
+    if score > MATE - MAX_PLY as i64 {
+        Some(MATE - score)
+    } else if score < MAX_PLY as i64 - MATE {
+        Some(-MATE - score)
+    } else {
+        None
+    }
+}
+
+/// Moves, rather than plies, to mate for a mate score, as shown in UCI
+/// and PGN. Negative when getting mated.
+pub fn mate_in_moves(score: i64) -> Option<i64> {
+    mate_in(score).map(|n| if n > 0 { (n + 1) / 2 } else { -((1 - n) / 2) })
+}
+
 pub fn evaluate(pos: &Pos) -> i64 {
     //return 0;
     let mut score = 0;
     //let mut sq = 1 << 63;
-    for w in pos.board.whites {
+    for w in pos.board.whites.squares() {
         let Pc(_, t) = pos.board.get(w).unwrap();
-        let sq_num = w.largest_bit() - 1;
-        let idx = (7-(sq_num/8))*8 + sq_num % 8; // index backwards for white
+        let idx = w.mirror().index(); // index backwards for white
         let pc_val = BASE_VALUES[t as usize] + match t {
             Pawn => PAWN[idx],
             Knight => KNIGHT[idx],
@@ -122,10 +152,9 @@ pub fn evaluate(pos: &Pos) -> i64 {
         };
         score += pc_val;
     }
-    for b in pos.board.blacks {
+    for b in pos.board.blacks.squares() {
         let Pc(_, t) = pos.board.get(b).unwrap();
-        let sq_num = b.largest_bit() - 1;
-        let idx = sq_num;
+        let idx = b.index();
         let pc_val = BASE_VALUES[t as usize] + match t {
             Pawn => PAWN[idx],
             Knight => KNIGHT[idx],
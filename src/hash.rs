@@ -1,77 +1,103 @@
+//! Zobrist keys for positions. A position's key is the xor of a random
+//! number for each piece on its square, the castling rights, the en passant
+//! file when a capture there is possible, and the side to move. `Pos` keeps
+//! its key up to date as moves are made.
 
-use rand;
-
+use types::Pc;
 use types::Color::*;
 
-use bitboard::BitBoard;
+use square::Square;
 use pos::Pos;
-use std::collections::HashMap;
-// use std::cell::RefCell;
-// use std::rc::Rc;
-use std::sync::Mutex;
-
-pub fn piece_hash(pc: usize, sq: BitBoard) -> u64 {
-    debug_assert!(sq.count_bits() == 1);
-    HASH_INIT[pc][sq.largest_bit() - 1]
+use magic::Prng;
+
+pub fn piece_hash(pc: usize, sq: Square) -> u64 {
+    KEYS.pieces[pc][sq.index()]
+}
+
+/// Key for a piece on a square
+pub fn piece(p: Pc, sq: Square) -> u64 {
+    let Pc(c, t) = p;
+    piece_hash(c as usize + t as usize, sq)
+}
+
+/// Key for a set of castling rights
+pub fn castling(rights: u8) -> u64 {
+    KEYS.castling[rights as usize & 0xf]
 }
 
-pub fn inc() {
-    let mut val = HASH.lock().unwrap();
-    val.insert(1, 666);
+/// Key for the en passant square, if a capture is possible there
+pub fn en_passant(sq: Option<Square>) -> u64 {
+    sq.map_or(0, |sq| KEYS.en_passant[sq.file()])
 }
 
+/// Key for Black to move
+pub fn side() -> u64 {
+    KEYS.side
+}
+
+/// Compute the key of a position from scratch
 pub fn full_hash(pos: &Pos) -> u64 {
     let mut res = 0;
 
     for (idx, bbs) in pos.board.pieces.iter().enumerate() {
-        for bb in *bbs {
-            res ^= piece_hash(idx, bb);
+        for sq in bbs.squares() {
+            res ^= piece_hash(idx, sq);
         }
     }
 
-    let r = match pos.turn {
+    res ^= castling(pos.castling_rights);
+    res ^= en_passant(pos.en_passant_capturable());
+    match pos.turn {
         White => res,
-        Black => !res
-    };
-
-    let mut hm = HASH.lock().unwrap();
-    if hm.contains_key(&r) {
-        //println!("HASH HIT!!! {} {:?}", r, *hm);
-        //panic!("saf");
-        //println!("{:?}", *hm);
-    } else {
-        hm.insert(r, 1);
+        Black => res ^ side()
     }
+}
 
-    r
+struct Keys {
+    pieces: [[u64; 64]; 12],
+    castling: [u64; 16],
+    en_passant: [u64; 8],
+    side: u64,
 }
 
 lazy_static! {
-    static ref HASH: Mutex<HashMap<u64, u64>> = {
-        Mutex::new(HashMap::new())
-    };
-
-    static ref HASH_INIT: [[u64; 64]; 12] = {
-
-        let mut hashes = [[0; 64]; 12];
+    static ref KEYS: Keys = {
+        // A fixed seed, so keys are the same on every run and can be
+        // compared between runs
+        let mut rng = Prng::new(1070372);
+        let mut keys = Keys {
+            pieces: [[0; 64]; 12],
+            castling: [0; 16],
+            en_passant: [0; 8],
+            side: 0,
+        };
 
         for pn in 0..12 {
             for sq in 0..64 {
-                hashes[pn][sq] = rand::random();
+                keys.pieces[pn][sq] = rng.next();
             }
         }
+        // Each right has its own key, and a set of rights is their xor
+        let rights: Vec<u64> = (0..4).map(|_| rng.next()).collect();
+        for r in 0..16 {
+            keys.castling[r] = (0..4).filter(|i| r & (1 << i) != 0).fold(0, |k, i| k ^ rights[i]);
+        }
+        for f in 0..8 {
+            keys.en_passant[f] = rng.next();
+        }
+        keys.side = rng.next();
 
-        hashes
+        keys
     };
 }
 
 #[test]
 fn full_hashing() {
-    let p1 =        Pos::from_fen("3r2k1/ppp2ppr/8/8/8/P4n1P/2P3q1/4K3 w KQkq - 0 1");
-    let p2 =        Pos::from_fen("3r2k1/ppp2ppr/8/8/8/P4n1P/2P3q1/4K3 w KQkq - 0 1");
-    let p_turn =    Pos::from_fen("3r2k1/ppp2ppr/8/8/8/P4n1P/2P3q1/4K3 b KQkq - 0 1");
-    let p_move =    Pos::from_fen("3r2k1/ppp2ppr/8/8/8/p4n1P/2P3q1/4K3 w KQkq - 0 1");
-    let p_cast =    Pos::from_fen("3r2k1/ppp2ppr/8/8/8/P4n1P/2P3q1/4K3 w Kkq - 0 1");
+    let p1 =        Pos::from_fen("3r2k1/ppp2ppr/8/8/8/P4n1P/2P3q1/4K3 w KQkq - 0 1").unwrap();
+    let p2 =        Pos::from_fen("3r2k1/ppp2ppr/8/8/8/P4n1P/2P3q1/4K3 w KQkq - 0 1").unwrap();
+    let p_turn =    Pos::from_fen("3r2k1/ppp2ppr/8/8/8/P4n1P/2P3q1/4K3 b KQkq - 0 1").unwrap();
+    let p_move =    Pos::from_fen("3r2k1/ppp2ppr/8/8/8/p4n1P/2P3q1/4K3 w KQkq - 0 1").unwrap();
+    let p_cast =    Pos::from_fen("3r2k1/ppp2ppr/8/8/8/P4n1P/2P3q1/4K3 w Kkq - 0 1").unwrap();
 
     let p1h = full_hash(&p1);
 
@@ -80,3 +106,45 @@ fn full_hashing() {
     assert!(p1h != full_hash(&p_move));
     assert!(p1h != full_hash(&p_cast));
 }
+
+#[test]
+fn incremental_hashing() {
+    use types::Move;
+
+    let play = |moves: &[&str]| {
+        let mut pos = Pos::start();
+        for m in moves {
+            let mv = Move::from_str(&pos, m).unwrap();
+            pos.make_move(mv);
+            assert_eq!(pos.hash, full_hash(&pos));
+        }
+        pos
+    };
+
+    // The same position reached by different move orders
+    let a = play(&["g1f3", "g8f6", "b1c3", "b8c6"]);
+    let b = play(&["b1c3", "b8c6", "g1f3", "g8f6"]);
+    assert_eq!(a.hash, b.hash);
+    assert!(a.hash != Pos::start().hash);
+
+    // Moving the king loses the castling rights
+    let c = play(&["e2e4", "e7e5", "e1e2", "e8e7", "e2e1", "e7e8"]);
+    let d = play(&["e2e4", "e7e5"]);
+    assert!(c.hash != d.hash);
+    assert_eq!(c.board, d.board);
+
+    // The en passant square only counts when a pawn can capture there
+    let e = play(&["e2e4", "d7d5", "e4e5", "f7f5"]);
+    let f = play(&["e2e4", "f7f5", "e4e5", "d7d5"]);
+    assert!(e.hash != f.hash);
+    let g = play(&["e2e4"]);
+    let h = Pos::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+    assert_eq!(g.hash, h.hash);
+
+    let mut pos = play(&["e2e4", "d7d5", "e4d5", "d8d5", "b1c3"]);
+    let key = pos.hash;
+    let mv = Move::from_str(&pos, "d5a5").unwrap();
+    pos.make_move(mv);
+    pos.unmake_move(mv);
+    assert_eq!(pos.hash, key);
+}
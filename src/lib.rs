@@ -8,7 +8,6 @@
 // extern crate bitflags;
 #[macro_use]
 extern crate lazy_static;
-extern crate rand;
 // extern crate ansi_term;
 // extern crate test;
 extern crate time;
@@ -16,13 +15,56 @@ extern crate time;
 pub mod types;
 pub mod board;
 pub mod bitboard;
+pub mod square;
+pub mod magic;
 pub mod eval;
+pub mod movelist;
 pub mod movegenerator;
 pub mod hash;
+pub mod tt;
+pub mod search;
 pub mod pos;
+pub mod san;
+pub mod pgn;
+pub mod epd;
 
+#[cfg(test)]
 use pos::Pos;
 
+/// Helpers shared by the tests
+#[cfg(test)]
+mod testing {
+    use pos::Pos;
+    use movegenerator;
+
+    /// Linear congruential generator, so the tests are the same on every run
+    pub struct Lcg(u64);
+
+    impl Lcg {
+        pub fn new(seed: u64) -> Lcg {
+            Lcg(seed)
+        }
+
+        pub fn next(&mut self) -> u64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            self.0
+        }
+    }
+
+    /// Play up to `plies` random legal moves, or until there are none.
+    /// `f` is called with every position reached, the first and last included.
+    pub fn random_playout<F: FnMut(&mut Pos)>(game: &mut Pos, seed: u64, plies: usize, mut f: F) {
+        let mut rng = Lcg::new(seed);
+        for _ in 0..plies {
+            let moves = movegenerator::legal_moves(game);
+            if moves.is_empty() { break; }
+            f(game);
+            game.make_move(moves[(rng.next() >> 33) as usize % moves.len()]);
+        }
+        f(game);
+    }
+}
+
 #[test]
 fn perft1() {
     let mut game1 = Pos::start();
@@ -37,50 +79,804 @@ fn perft1() {
 
 #[test]
 fn perft2() {
-    let mut game2 = Pos::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1");
+    let mut game2 = Pos::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").unwrap();
     assert_eq!(game2.perft(1), 14);
     assert_eq!(game2.perft(2), 191);
     assert_eq!(game2.perft(3), 2812);
     assert_eq!(game2.perft(4), 43238);
 }
 
+#[test]
 fn perft3() {
-    let mut game2 = Pos::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+    let mut game2 = Pos::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
 
-    println!("{}", game2);
-    assert_eq!(game2.perft(1), 48); // castling missing
+    assert_eq!(game2.perft(1), 48);
     assert_eq!(game2.perft(2), 2039);
     assert_eq!(game2.perft(3), 97862);
-    assert_eq!(game2.perft(4), 4085603);
-    assert_eq!(game2.perft(5), 193690690);
+    //assert_eq!(game2.perft(4), 4085603);
+    //assert_eq!(game2.perft(5), 193690690);
+}
+
+#[test]
+fn perft_castling() {
+    let mut game = Pos::from_fen("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+    assert_eq!(game.perft(1), 26);
+    assert_eq!(game.perft(2), 112);
+    assert_eq!(game.perft(3), 3189);
+
+    let mut game = Pos::from_fen("r3k2r/8/8/8/8/8/8/4K3 w kq - 0 1").unwrap();
+    assert_eq!(game.perft(1), 5);
+    assert_eq!(game.perft(2), 130);
+    assert_eq!(game.perft(3), 782);
+
+    let mut game = Pos::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    assert_eq!(game.perft(1), 26);
+    assert_eq!(game.perft(2), 568);
+    assert_eq!(game.perft(3), 13744);
+}
+
+#[test]
+fn castling_rights_restored() {
+    let mut game = Pos::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    let moves = movegenerator::legal_moves(&game);
+    for m in moves {
+        game.make_move(m);
+        for r in movegenerator::legal_moves(&game) {
+            game.make_move(r);
+            game.unmake_move(r);
+        }
+        game.unmake_move(m);
+        assert_eq!(game.castling_rights, 0b1111);
+        assert_eq!(game.board.occupied.count_bits(), 6);
+    }
+}
+
+#[test]
+fn perft_chess960() {
+    let mut game = Pos::from_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9").unwrap();
+    assert!(game.chess960);
+    assert_eq!(game.perft(1), 21);
+    assert_eq!(game.perft(2), 528);
+    assert_eq!(game.perft(3), 12189);
+
+    let mut game = Pos::from_fen("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9").unwrap();
+    assert_eq!(game.perft(1), 21);
+    assert_eq!(game.perft(2), 807);
+    assert_eq!(game.perft(3), 18002);
+}
+
+#[test]
+fn chess960_castling() {
+    use types::Move;
+
+    // King on b1 castles queenside with the rook on a1, kingside with the rook on f1
+    let mut game = Pos::from_fen("r3k2r/8/8/8/8/8/8/RK3R2 w FAha - 0 1").unwrap();
+    assert_eq!(game.to_fen(), "r3k2r/8/8/8/8/8/8/RK3R2 w KQkq - 0 1");
+    assert_eq!(game.to_shredder_fen(), "r3k2r/8/8/8/8/8/8/RK3R2 w FAha - 0 1");
+
+    let ooo = Move::from_str(&game, "b1a1").unwrap();
+    assert!(ooo.castling.is_some());
+    assert_eq!(ooo.to_uci(&game), "b1a1");
+    assert_eq!(ooo.to_san(&game), "O-O-O");
+    assert!(movegenerator::legal_moves(&game).contains(&ooo));
+    game.make_move(ooo);
+    assert_eq!(game.to_fen(), "r3k2r/8/8/8/8/8/8/2KR1R2 b kq - 1 1");
+    game.undo_move();
+    assert_eq!(game.to_fen(), "r3k2r/8/8/8/8/8/8/RK3R2 w KQkq - 0 1");
+
+    let oo = Move::from_san(&game, "O-O").unwrap();
+    assert_eq!(oo.to_uci(&game), "b1f1");
+    assert_eq!(Move::from_str(&game, "b1f1"), Some(oo));
+    game.make_move(oo);
+    assert_eq!(game.to_fen(), "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1");
+
+    // The rook on b1 shields the king from the queen until it castles away
+    let game = Pos::from_fen("4k3/8/8/8/8/8/8/qRK5 w B - 0 1").unwrap();
+    assert!(game.chess960);
+    assert!(movegenerator::legal_moves(&game).iter().all(|m| m.castling.is_none()));
+
+    // X-FEN uses a file letter when another rook is further out
+    let game = Pos::from_fen("4k3/8/8/8/8/8/8/RR2K1RR w BG - 0 1").unwrap();
+    assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/RR2K1RR w GB - 0 1");
+    assert_eq!(Pos::from_fen("4k3/8/8/8/8/8/8/RR2K1RR w KQ - 0 1").unwrap().to_shredder_fen(),
+               "4k3/8/8/8/8/8/8/RR2K1RR w HA - 0 1");
+
+    // Standard chess keeps the e1g1 notation
+    let game = Pos::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    assert!(!game.chess960);
+    let oo = Move::from_str(&game, "e1g1").unwrap();
+    assert_eq!(oo.to_uci(&game), "e1g1");
+    assert_eq!(game.to_shredder_fen(), "r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1");
+}
+
+#[test]
+fn chess960_start_positions() {
+    assert_eq!(Pos::chess960_start(518).unwrap().to_fen(), Pos::start().to_fen());
+    assert_eq!(Pos::chess960_start(0).unwrap().to_fen(),
+               "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1");
+    assert_eq!(Pos::chess960_start(959).unwrap().to_fen(),
+               "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq - 0 1");
+    assert!(Pos::chess960_start(960).is_none());
+
+    let mut fens: Vec<String> = (0..960).map(|n| Pos::chess960_start(n).unwrap().to_fen()).collect();
+    fens.sort();
+    fens.dedup();
+    assert_eq!(fens.len(), 960);
+
+    let mut game = Pos::chess960_start(0).unwrap();
+    assert_eq!(game.perft(1), 20);
+    assert_eq!(game.perft(2), 400);
+}
+
+#[test]
+fn packed_moves() {
+    use types::{PackedMove, Move};
+
+    assert_eq!(std::mem::size_of::<PackedMove>(), 2);
+    assert!(PackedMove::null().is_null());
+    assert_eq!(PackedMove::null().unpack(&Pos::start()), None);
+
+    let fens = ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1",
+                "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+                "r3k2r/8/8/8/8/8/8/RK3R2 w FAha - 0 1"];
+    for fen in fens.iter() {
+        let mut game = Pos::from_fen(fen).unwrap();
+        for m in movegenerator::legal_moves(&game) {
+            let p = m.pack();
+            assert!(!p.is_null());
+            assert_eq!(p.unpack(&game), Some(m));
+            assert_eq!(p.to_string(), m.to_str());
+            assert_eq!(PackedMove::from_bits(p.bits()), p);
+
+            // A move from another position does not fit
+            game.make_move(m);
+            assert_eq!(p.unpack(&game), None);
+            game.unmake_move(m);
+        }
+    }
+
+    let game = Pos::from_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N w - - 0 1").unwrap();
+    let m = Move::from_str(&game, "b7a8r").unwrap().pack();
+    assert!(m.is_capture() && !m.is_castling() && !m.is_en_passant());
+    assert_eq!(m.promotion(), Some(types::PieceType::Rook));
+    assert_eq!((m.from_sq().index(), m.to_sq().index()), (49, 56));
+}
+
+#[test]
+fn mailbox_in_sync() {
+    use types::{Pc, Color, PieceType};
+    use square::Square;
+
+    fn check(game: &Pos) {
+        for i in 0..64 {
+            let sq = Square::from_index(i);
+            let mut found = None;
+            for &c in [Color::White, Color::Black].iter() {
+                for &t in [PieceType::Pawn, PieceType::Knight, PieceType::Bishop,
+                           PieceType::Rook, PieceType::Queen, PieceType::King].iter() {
+                    if (game.board.get_squares(Pc(c, t)) & sq.bb()).has_bits() {
+                        assert_eq!(found, None);
+                        found = Some(Pc(c, t));
+                    }
+                }
+            }
+            assert_eq!(game.board.get(sq), found);
+            assert_eq!((game.board.occupied & sq.bb()).has_bits(), found.is_some());
+        }
+    }
+
+    let mut game = Pos::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1").unwrap();
+    for m in movegenerator::legal_moves(&game) {
+        game.make_move(m);
+        check(&game);
+        for r in movegenerator::legal_moves(&game) {
+            game.make_move(r);
+            check(&game);
+            game.unmake_move(r);
+        }
+        game.unmake_move(m);
+    }
+    check(&game);
+}
+
+#[test]
+fn legal_moves_match_make_unmake() {
+    // The moves that don't leave the king attacked once played
+    fn reference(game: &mut Pos) -> Vec<String> {
+        let mut legal = Vec::new();
+        for m in movegenerator::all_moves(game) {
+            game.make_move(m);
+            let (atk, _) = movegenerator::all_moves_and_attack_map(game);
+            let king = game.board.get_squares(types::Pc(game.turn.other(), types::PieceType::King));
+            if (king & atk).is_empty() {
+                legal.push(format!("{:?}", m));
+            }
+            game.unmake_move(m);
+        }
+        legal.sort();
+        legal
+    }
+
+    let fens = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "8/8/8/2k5/3Pp3/8/8/4K2Q b - d3 0 1",
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+    ];
+
+    for fen in fens.iter() {
+        for seed in 0..8 {
+            let mut game = Pos::from_fen(fen).unwrap();
+            testing::random_playout(&mut game, seed, 80, |game| {
+                let mut legal: Vec<String> = movegenerator::legal_moves(game)
+                    .iter().map(|m| format!("{:?}", m)).collect();
+                legal.sort();
+                assert_eq!(legal, reference(game), "{}", game.to_fen());
+            });
+        }
+    }
+}
+
+#[test]
+fn staged_moves() {
+    use types::Move;
+    use movegenerator::StagedMoves;
+
+    fn staged(game: &Pos, hash_move: Option<Move>, killers: [Option<Move>; 2]) -> Vec<Move> {
+        let mut picker = StagedMoves::new(hash_move, killers);
+        let mut moves = Vec::new();
+        while let Some(m) = picker.next(game) {
+            moves.push(m);
+        }
+        moves
+    }
+
+    let fens = ["r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1",
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                "4k3/8/8/8/8/3n4/8/4K2r w - - 0 1"];
+    for fen in fens.iter() {
+        let game = Pos::from_fen(fen).unwrap();
+        let mut legal: Vec<String> = movegenerator::legal_moves(&game)
+            .iter().map(|m| format!("{:?}", m)).collect();
+        legal.sort();
+
+        let mut all: Vec<String> = staged(&game, None, [None, None])
+            .iter().map(|m| format!("{:?}", m)).collect();
+        all.sort();
+        assert_eq!(all, legal);
+
+        // Captures come first, and are not repeated as hash or killer moves
+        let moves = staged(&game, None, [None, None]);
+        let captures = moves.iter().take_while(|m| movegenerator::is_noisy(m)).count();
+        assert!(moves[captures..].iter().all(|m| !movegenerator::is_noisy(m)));
+        let hash = moves.last().cloned();
+        let killer = moves.get(captures).cloned();
+        let ordered = staged(&game, hash, [killer, hash]);
+        assert_eq!(ordered.len(), moves.len());
+        assert_eq!(ordered[0], hash.unwrap());
+        if captures < moves.len() - 1 {
+            assert_eq!(ordered[captures + 1], killer.unwrap());
+        }
+    }
+
+    let game = Pos::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+    let first = staged(&game, None, [None, None])[0];
+    // The bishop on a6 is the most valuable victim
+    assert_eq!(first.to_str(), "e2a6");
+
+    // Moves from elsewhere are skipped
+    let e2e4 = Move::from_str(&Pos::start(), "e2e4").unwrap();
+    let moves = staged(&game, Some(e2e4), [Some(e2e4), None]);
+    assert_eq!(moves.len(), 48);
+    assert!(!moves.contains(&e2e4));
+}
+
+#[test]
+fn attack_queries() {
+    use types::Color::*;
+    use square::Square;
+    use bitboard::BitBoard;
+
+    let sq = |s: &str| s.parse::<Square>().unwrap();
+    let bb = |s| BitBoard::from_str(s).unwrap();
+
+    let game = Pos::from_fen("4k3/8/8/1b6/8/2N5/3P4/r3K2R w K - 0 1").unwrap();
+    assert!(movegenerator::in_check(&game));
+    assert_eq!(movegenerator::checkers(&game), bb("a1"));
+    assert_eq!(movegenerator::attackers_to(&game, sq("e4"), White), bb("c3"));
+    assert_eq!(movegenerator::attackers_to(&game, sq("e3"), White), bb("d2"));
+    assert_eq!(movegenerator::attackers_to(&game, sq("e2"), White), bb("c3") | bb("e1"));
+    assert_eq!(movegenerator::attackers_to(&game, sq("f1"), Black), bb("b5"));
+    // Defended pieces count as attacked, and the king blocks the rook
+    assert!(movegenerator::is_square_attacked(&game, sq("c3"), White));
+    assert!(movegenerator::is_square_attacked(&game, sq("b5"), White));
+    assert!(!movegenerator::is_square_attacked(&game, sq("h1"), Black));
+    assert!(!movegenerator::is_square_attacked(&game, sq("d2"), Black));
+
+    // A double check
+    let game = Pos::from_fen("4k3/8/8/8/8/3n4/8/4K2r w - - 0 1").unwrap();
+    assert_eq!(movegenerator::checkers(&game), bb("d3") | bb("h1"));
+    assert!(movegenerator::legal_moves(&game).iter().all(|m| m.from == sq("e1")));
+
+    let game = Pos::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+    assert!(!movegenerator::in_check(&game));
+    assert_eq!(movegenerator::checkers(&game), BitBoard::empty());
+    assert_eq!(movegenerator::attackers_to(&game, sq("f3"), White), bb("e2") | bb("g2") | bb("g1"));
+}
+
+#[test]
+fn perft_en_passant() {
+    let mut game = Pos::from_fen("rnbqkb1r/ppppp1pp/7n/4Pp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3").unwrap();
+    assert_eq!(game.perft(1), 31);
+    assert_eq!(game.perft(2), 570);
+    assert_eq!(game.perft(3), 17546);
+}
+
+#[test]
+fn en_passant_exposing_king() {
+    let game = Pos::from_fen("8/8/8/8/k2Pp2Q/8/8/3K4 b - d3 0 1").unwrap();
+    let moves = movegenerator::legal_moves(&game);
+    assert!(moves.iter().all(|m| !m.en_passant));
+
+    let game = Pos::from_fen("8/8/8/8/k2Pp3/8/8/3K4 b - d3 0 1").unwrap();
+    let moves = movegenerator::legal_moves(&game);
+    assert_eq!(moves.iter().filter(|m| m.en_passant).count(), 1);
+}
+
+#[test]
+fn en_passant_make_unmake() {
+    let mut game = Pos::from_fen("rnbqkb1r/ppppp1pp/7n/4Pp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3").unwrap();
+    let mv = movegenerator::legal_moves(&game).into_iter().find(|m| m.en_passant).unwrap();
+    assert_eq!(mv.to_str(), "e5f6");
+
+    game.make_move(mv);
+    assert_eq!(game.board.occupied.count_bits(), 31);
+    assert_eq!(game.en_passant, None);
+    game.unmake_move(mv);
+    assert_eq!(game.board.occupied.count_bits(), 32);
+    assert_eq!(game.en_passant, "f6".parse::<square::Square>().ok());
+}
+
+#[test]
+fn perft_promotion() {
+    let mut game = Pos::from_fen("8/PPPk4/8/8/8/8/4Kppp/8 w - - 0 1").unwrap();
+    assert_eq!(game.perft(1), 18);
+    assert_eq!(game.perft(2), 270);
+    assert_eq!(game.perft(3), 4699);
+
+    let mut game = Pos::from_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1").unwrap();
+    assert_eq!(game.perft(1), 24);
+    assert_eq!(game.perft(2), 496);
+    assert_eq!(game.perft(3), 9483);
+
+    let mut game = Pos::from_fen("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1").unwrap();
+    assert_eq!(game.perft(1), 6);
+    assert_eq!(game.perft(2), 264);
+    assert_eq!(game.perft(3), 9467);
+}
+
+#[test]
+fn parse_promotion() {
+    let game = Pos::from_fen("8/P6k/8/8/8/8/8/K7 w - - 0 1").unwrap();
+    for s in ["a7a8q", "a7a8r", "a7a8b", "a7a8n"].iter() {
+        let mv = types::Move::from_str(&game, s).unwrap();
+        assert_eq!(mv.to_str(), *s);
+    }
+    assert_eq!(types::Move::from_str(&game, "a7a8N").unwrap().to_str(), "a7a8n");
+
+    assert_eq!(types::Move::from_str(&game, "a7a8"), None);
+    assert_eq!(types::Move::from_str(&game, "a7a8k"), None);
+    assert_eq!(types::Move::from_str(&game, "a7a8x"), None);
+    assert_eq!(types::Move::from_str(&game, "a1a2q"), None);
+    assert_eq!(types::Move::from_str(&game, "a7a8qq"), None);
+    assert_eq!(types::Move::from_str(&game, "A7a8q"), None);
+    assert_eq!(types::Move::from_str(&game, "a7"), None);
+}
+
+#[test]
+fn parse_illegal_moves() {
+    use types::Move;
+
+    // Well-formed, but not legal in the position
+    let game = Pos::start();
+    assert_eq!(Move::from_str(&game, "e2e5"), None);
+    assert_eq!(Move::from_str(&game, "e1g1"), None);
+    assert_eq!(Move::from_str(&game, "e7e5"), None);
+    assert!(Move::from_str(&game, "e2e4").is_some());
+
+    // Castling needs the right and the rook
+    let game = Pos::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(Move::from_str(&game, "e1g1"), None);
+    let game = Pos::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+    assert!(Move::from_str(&game, "e1g1").unwrap().castling.is_some());
+
+    // Pinned piece
+    let game = Pos::from_fen("4k3/4r3/8/8/8/8/4N3/4K3 w - - 0 1").unwrap();
+    assert_eq!(Move::from_str(&game, "e2c3"), None);
+}
+
+#[test]
+fn unmake_restores_everything() {
+    let fens = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        "rnbqkb1r/ppppp1pp/7n/4Pp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+    ];
+    for fen in fens.iter() {
+        let mut game = Pos::from_fen(fen).unwrap();
+        let mut snapshots = Vec::new();
+        testing::random_playout(&mut game, 12345, 40, |game| snapshots.push(format!("{:?}", game)));
+        assert_eq!(snapshots.pop().unwrap(), format!("{:?}", game));
+        while let Some(snapshot) = snapshots.pop() {
+            assert!(game.undo_move().is_some());
+            assert_eq!(format!("{:?}", game), snapshot);
+        }
+        assert_eq!(game.undo_move(), None);
+        assert_eq!(game.board, Pos::from_fen(fen).unwrap().board);
+    }
+}
+
+#[test]
+fn halfmove_clock() {
+    let mut game = Pos::start();
+    for (s, hm) in [("g1f3", 1), ("g8f6", 2), ("e2e4", 0), ("f6e4", 0), ("f3g1", 1)].iter() {
+        let mv = types::Move::from_str(&game, s).unwrap();
+        game.make_move(mv);
+        assert_eq!(game.halfmoves, *hm);
+    }
+    game.undo_move();
+    game.undo_move();
+    assert_eq!(game.halfmoves, 0);
+    game.undo_move();
+    assert_eq!(game.halfmoves, 2);
+}
+
+#[test]
+fn outcomes() {
+    use types::Outcome::*;
+    use types::Color::*;
+
+    let game = Pos::from_fen("3r2k1/ppp2ppr/8/8/8/P4n1P/2P3q1/4K3 w - - 0 1").unwrap();
+    assert_eq!(game.outcome(), Some(Checkmate(Black)));
+    assert_eq!(game.outcome().unwrap().result_str(), "0-1");
+
+    let game = Pos::from_fen("7k/8/8/8/8/ppp5/2p5/K7 w - - 0 1").unwrap();
+    assert_eq!(game.outcome(), Some(Stalemate));
+
+    let game = Pos::from_fen("8/8/8/8/8/8/8/KB5k w - - 0 1").unwrap();
+    assert_eq!(game.outcome(), Some(InsufficientMaterial));
+    let game = Pos::from_fen("8/8/8/8/8/8/8/KB1b3k w - - 0 1").unwrap();
+    assert_eq!(game.outcome(), Some(InsufficientMaterial));
+    let game = Pos::from_fen("8/8/8/8/8/8/8/KBb4k w - - 0 1").unwrap();
+    assert_eq!(game.outcome(), None);
+    let game = Pos::from_fen("8/8/8/8/8/8/8/KNn4k w - - 0 1").unwrap();
+    assert_eq!(game.outcome(), None);
+
+    let game = Pos::from_fen("8/8/8/8/8/8/8/KR5k w - - 99 80").unwrap();
+    assert_eq!(game.outcome(), None);
+    let game = Pos::from_fen("8/8/8/8/8/8/8/KR5k w - - 100 80").unwrap();
+    assert_eq!(game.outcome(), Some(FiftyMoveRule));
+
+    let mut game = Pos::start();
+    for i in 0..4 {
+        for s in ["g1f3", "g8f6", "f3g1", "f6g8"].iter() {
+            let mv = types::Move::from_str(&game, s).unwrap();
+            game.make_move(mv);
+        }
+        assert_eq!(game.repetitions(), i + 2);
+        if i == 0 { assert_eq!(game.outcome(), None); }
+        if i == 1 { assert_eq!(game.outcome(), Some(ThreefoldRepetition)); }
+    }
+    assert_eq!(game.outcome(), Some(FivefoldRepetition));
+    game.undo_move();
+    assert_eq!(game.repetitions(), 4);
+    assert_eq!(game.outcome(), Some(ThreefoldRepetition));
+}
+
+#[test]
+fn fen_round_trip() {
+    let fens = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "rnbqkb1r/ppppp1pp/7n/4Pp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "8/7p/p5pb/4k3/P1pPn3/8/P5PP/1rB2RK1 b - d3 0 28",
+        "r3k2r/8/8/8/8/8/8/4K3 w kq - 12 40",
+        "8/8/8/8/8/8/8/KR5k b - - 99 80",
+    ];
+    for fen in fens.iter() {
+        assert_eq!(Pos::from_fen(fen).unwrap().to_fen(), *fen);
+    }
+
+    let mut game = Pos::from_fen(fens[1]).unwrap();
+    testing::random_playout(&mut game, 99, 60, |game| {
+        let copy = Pos::from_fen(&game.to_fen()).unwrap();
+        assert_eq!(copy.board, game.board);
+        assert_eq!(copy.turn, game.turn);
+        assert_eq!(copy.castling_rights, game.castling_rights);
+        assert_eq!(copy.en_passant, game.en_passant);
+        assert_eq!(copy.halfmoves, game.halfmoves);
+        assert_eq!(copy.moves, game.moves);
+        assert_eq!(copy.to_fen(), game.to_fen());
+    });
+}
+
+#[test]
+fn fen_errors() {
+    use pos::FenField;
+
+    let err = |fen: &str| Pos::from_fen(fen).unwrap_err().field;
+    assert_eq!(err(""), FenField::Board);
+    assert_eq!(err("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1"), FenField::Board);
+    assert_eq!(err("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), FenField::Board);
+    assert_eq!(err("rnbqkbnr/ppppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), FenField::Board);
+    assert_eq!(err("rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), FenField::Board);
+    assert_eq!(err("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1"), FenField::Board);
+    assert_eq!(err("rnbq1bnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1"), FenField::Board);
+    assert_eq!(err("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR"), FenField::Turn);
+    assert_eq!(err("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1"), FenField::Turn);
+    assert_eq!(err("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQxq - 0 1"), FenField::Castling);
+    assert_eq!(err("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KK - 0 1"), FenField::Castling);
+    assert_eq!(err("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1"), FenField::EnPassant);
+    assert_eq!(err("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq z6 0 1"), FenField::EnPassant);
+    assert_eq!(err("rnbqkbnr/pppppppp/8/4P3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 1"), FenField::EnPassant);
+    assert_eq!(err("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq d3 0 1"), FenField::EnPassant);
+    assert!(Pos::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").is_ok());
+    assert_eq!(err("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - -1 1"), FenField::Halfmoves);
+    assert_eq!(err("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0"), FenField::Moves);
+    assert_eq!(err("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 x"), FenField::Moves);
+
+    let e = Pos::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQxq - 0 1").unwrap_err();
+    assert_eq!(e.to_string(), "invalid FEN castling rights: invalid castling rights 'KQxq'");
+
+    let start = Pos::start().to_fen();
+    assert_eq!(Pos::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -").unwrap().to_fen(), start);
+    assert_eq!(Pos::from_fen("  rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR  w KQkq - 0 1 ").unwrap().to_fen(), start);
+}
+
+#[test]
+fn validate() {
+    use pos::Problem;
+    use types::CastlingMove::*;
+    use types::Color::*;
+    use square::Square;
+
+    let problems = |fen: &str| Pos::from_fen(fen).unwrap().validate();
+    let sq = |s: &str| s.parse::<Square>().unwrap();
+
+    assert_eq!(Pos::start().validate(), vec![]);
+    assert_eq!(problems("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"), vec![]);
+    assert_eq!(problems("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"), vec![]);
+    assert_eq!(Pos::chess960_start(0).unwrap().validate(), vec![]);
+
+    assert_eq!(problems("4k3/8/8/8/8/8/8/K3K3 w - - 0 1"), vec![Problem::KingCount(White, 2)]);
+    assert_eq!(problems("4k3/8/8/8/PPPPPPPP/P7/8/4K3 w - - 0 1"), vec![Problem::TooManyPawns(White)]);
+    assert_eq!(problems("P3k3/8/8/8/8/8/8/4K2p w - - 0 1"),
+               vec![Problem::PawnOnBackRank(sq("h1")), Problem::PawnOnBackRank(sq("a8"))]);
+    assert_eq!(problems("4k3/8/8/8/8/8/8/4K2r b - - 0 1"), vec![Problem::OpponentInCheck]);
+    assert_eq!(problems("4k3/8/8/8/8/8/8/4K2r w - - 0 1"), vec![]);
+    assert_eq!(problems("r3k2r/8/8/8/8/8/3K4/R6R w KQkq - 0 1"),
+               vec![Problem::CastlingKingMoved(WhiteKingside), Problem::CastlingKingMoved(WhiteQueenside)]);
+    assert_eq!(problems("r3k3/8/8/8/8/8/8/R3K2R w KQkq - 0 1"), vec![Problem::CastlingRookMoved(BlackKingside)]);
+    assert_eq!(problems("rnbqkb1r/ppppp1pp/5n2/5p2/4P3/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 2"),
+               vec![Problem::InvalidEnPassant(sq("f6"))]);
+
+    // Without a pawn in front of it, the en passant square is refused by
+    // the parser already
+    let mut p = Pos::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2").unwrap();
+    p.en_passant = Some(sq("d6"));
+    assert_eq!(p.validate(), vec![Problem::InvalidEnPassant(sq("d6"))]);
+
+    let mut p = Pos::start();
+    p.en_passant = Some(sq("e4"));
+    assert_eq!(p.validate(), vec![Problem::InvalidEnPassant(sq("e4"))]);
+    assert_eq!(Problem::OpponentInCheck.to_string(), "the side not to move is in check");
 }
 
 #[test]
 fn dont_move_into_check() {
-    let mut game = Pos::from_fen("8/8/8/8/8/ppp5/2p5/K7 w KQkq - 0 1");
-    let (_, nodes, best_move) = game.negamax_start(4);
+    let mut game = Pos::from_fen("7k/8/8/8/8/ppp5/2p5/K7 w KQkq - 0 1").unwrap();
+    let (_, nodes, best_move) = game.negamax_start(4, &mut tt::TranspositionTable::new(1));
     assert_eq!(best_move, None);
 }
 
 #[test]
 fn dont_move_making_discovered_check() {
-    let mut game = Pos::from_fen("P7/P7/P7/P7/P7/P7/P6r/KP5r w KQkq - 0 1");
+    let mut game = Pos::from_fen("P6k/P7/P7/P7/P7/P7/P6r/KP5r w KQkq - 0 1").unwrap();
     println!("{}", game);
-    let (_, nodes, best_move) = game.negamax_start(4);
+    let (_, nodes, best_move) = game.negamax_start(4, &mut tt::TranspositionTable::new(1));
     assert_eq!(best_move, None);
 }
 
 
 #[test]
 fn is_checkmate() {
-    let game = Pos::from_fen("3r2k1/ppp2ppr/8/8/8/P4n1P/2P3q1/4K3 w KQkq - 0 1");
-    let (_, nodes, best_move) = game.negamax_start(4);
+    let game = Pos::from_fen("3r2k1/ppp2ppr/8/8/8/P4n1P/2P3q1/4K3 w KQkq - 0 1").unwrap();
+    let (_, nodes, best_move) = game.negamax_start(4, &mut tt::TranspositionTable::new(1));
     assert_eq!(best_move, None);
 }
 
+#[test]
+fn alpha_beta_matches_minimax() {
+    use search::{Search, Limits};
+
+    // Plain minimax, visiting every node, down to a quiescence search with
+    // the full window
+    fn minimax(game: &mut Pos, depth: usize, ply: usize, search: &mut Search) -> i64 {
+        if depth == 0 {
+            return game.quiescence(ply, -eval::INFINITY, eval::INFINITY, search).0;
+        }
+        search.visit();
+        let moves = movegenerator::legal_moves(game);
+        if moves.is_empty() {
+            return if movegenerator::in_check(game) { ply as i64 - eval::MATE } else { 0 };
+        }
+        let mut best = -eval::INFINITY;
+        for m in moves {
+            game.make_move(m);
+            best = std::cmp::max(best, -minimax(game, depth - 1, ply + 1, search));
+            game.unmake_move(m);
+        }
+        best
+    }
+
+    let fens = ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+                "r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 2 3",
+                "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1"];
+    for fen in fens.iter() {
+        let mut game = Pos::from_fen(fen).unwrap();
+        let mut tt = tt::TranspositionTable::new(1);
+        let mut search = Search::new(&game, Limits::default(), &mut tt);
+        let expected = minimax(&mut game, 3, 0, &mut search);
+        let minimax_nodes = search.nodes;
+
+        let (score, nodes, best_move) = game.negamax_start(3, &mut tt::TranspositionTable::new(1));
+        assert_eq!(score, expected, "{}", fen);
+        assert!(nodes * 2 < minimax_nodes, "{}: {} nodes, minimax {}", fen, nodes, minimax_nodes);
+
+        // The best move is one of those with the best score
+        let m = best_move.unwrap();
+        game.make_move(m);
+        assert_eq!(-minimax(&mut game, 2, 1, &mut search), expected);
+    }
+
+    // Mate is found, and scored by its distance
+    let game = Pos::from_fen("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap();
+    let (score, _, best_move) = game.negamax_start(3, &mut tt::TranspositionTable::new(1));
+    assert_eq!(score, eval::MATE - 1);
+    assert_eq!(best_move.unwrap().to_str(), "d1d8");
+    assert_eq!(eval::mate_in(score), Some(1));
+
+    // Stalemate is a draw, not a loss
+    let game = Pos::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    assert_eq!(game.negamax_start(2, &mut tt::TranspositionTable::new(1)), (0, 1, None));
+}
+
+#[test]
+fn quiescence_search() {
+    use types::Color::White;
+    use search::{Search, Limits};
+
+    let mut tt = tt::TranspositionTable::new(1);
+    let mut search = Search::new(&Pos::start(), Limits::default(), &mut tt);
+
+    // Rook takes the pawn, but not the pawn defended by another
+    let mut game = Pos::from_fen("4k3/8/3p4/4p2p/8/8/8/4RK2 w - - 0 1").unwrap();
+    let stand_pat = eval::evaluate(&game);
+    let (score, nodes) = game.quiescence(0, -eval::INFINITY, eval::INFINITY, &mut search);
+    assert_eq!(score, stand_pat);
+    assert!(nodes > 1);
+    let (_, _, best_move) = game.negamax_start(1, &mut tt::TranspositionTable::new(1));
+    assert_ne!(best_move.unwrap().to_str(), "e1e5");
+
+    let mut game = Pos::from_fen("4k3/8/8/4p2p/8/8/8/4RK2 w - - 0 1").unwrap();
+    let (score, _) = game.quiescence(0, -eval::INFINITY, eval::INFINITY, &mut search);
+    assert!(score > eval::evaluate(&game) + 50);
+    let (_, _, best_move) = game.negamax_start(1, &mut tt::TranspositionTable::new(1));
+    assert_eq!(best_move.unwrap().to_str(), "e1e5");
+
+    // Nothing to capture: the static evaluation, from the side to move
+    let mut game = Pos::from_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+    assert_eq!(game.quiescence(0, -eval::INFINITY, eval::INFINITY, &mut search), (-eval::evaluate(&game), 1));
+
+    // In check, evasions are searched, and none is mate
+    let mut game = Pos::from_fen("R5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1").unwrap();
+    assert_eq!(game.quiescence(3, -eval::INFINITY, eval::INFINITY, &mut search).0, 3 - eval::MATE);
+    let mut game = Pos::from_fen("R5k1/5p1p/8/8/8/8/5PPP/6K1 b - - 0 1").unwrap();
+    let (score, nodes) = game.quiescence(0, -eval::INFINITY, eval::INFINITY, &mut search);
+    assert!(nodes > 1 && score > -eval::MATE + 100);
+
+    // A position out of the window is cut off by standing pat
+    let mut game = Pos::from_fen("4k3/8/3p4/4p2p/8/8/8/4RK2 w - - 0 1").unwrap();
+    assert_eq!(game.quiescence(0, -eval::INFINITY, stand_pat - 1, &mut search), (stand_pat, 1));
+    assert_eq!(game.turn, White);
+}
+
+#[test]
+fn iterative_deepening() {
+    use std::time::Duration;
+    use search::{Limits, Iteration};
+
+    let game = Pos::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+    let mut tt = tt::TranspositionTable::new(1);
+    let mut reports: Vec<Iteration> = Vec::new();
+    let limits = Limits { depth: Some(4), ..Limits::default() };
+    let last = search::iterative_deepening(&game, limits, &mut tt, |it| reports.push(it.clone())).unwrap();
+    assert_eq!(reports.iter().map(|it| it.depth).collect::<Vec<usize>>(), vec![1, 2, 3, 4]);
+    assert!(reports.windows(2).all(|w| w[0].nodes < w[1].nodes));
+    assert_eq!(last.depth, 4);
+    assert_eq!(last.nodes, reports[3].nodes);
+
+    // The principal variation is a line of legal moves
+    let mut pos = game.duplicate();
+    assert!(!last.pv.is_empty() && last.pv.len() <= 4);
+    for &m in last.pv.iter() {
+        assert!(movegenerator::is_legal(&pos, m));
+        pos.make_move(m);
+    }
+
+    // The same score as a single search of that depth, and the last
+    // iteration takes fewer nodes thanks to the earlier ones
+    let (score, nodes, _) = game.negamax_start(4, &mut tt::TranspositionTable::new(1));
+    assert_eq!(last.score, score);
+    let last_nodes = last.nodes - reports[2].nodes;
+    assert!(last_nodes < nodes, "{} nodes, {} without iterative deepening", last_nodes, nodes);
+
+    // The first iteration is always finished, so there is a move to play
+    for limits in [Limits { nodes: Some(1), ..Limits::default() },
+                   Limits { time: Some(Duration::from_millis(0)), ..Limits::default() }].iter() {
+        let it = search::iterative_deepening(&game, *limits, &mut tt, |_| {}).unwrap();
+        assert_eq!(it.depth, 1);
+        assert!(it.best_move().is_some());
+    }
+    let limits = Limits { nodes: Some(10000), ..Limits::default() };
+    let it = search::iterative_deepening(&game, limits, &mut tt::TranspositionTable::new(1), |_| {}).unwrap();
+    assert!(it.depth == 2 && it.nodes < 10000);
+
+    // Searching stops at a mate
+    let game = Pos::from_fen("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap();
+    let it = search::iterative_deepening(&game, Limits::default(), &mut tt, |_| {}).unwrap();
+    assert_eq!((it.depth, it.score), (1, eval::MATE - 1));
+    assert_eq!(it.best_move().unwrap().to_str(), "d1d8");
+
+    // No moves, no result
+    let game = Pos::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    assert!(search::iterative_deepening(&game, Limits::default(), &mut tt, |_| {}).is_none());
+
+    assert_eq!(Limits::time_for_move(Duration::from_secs(60), Duration::from_secs(1), None),
+               Duration::from_millis(2750));
+    assert_eq!(Limits::time_for_move(Duration::from_millis(40), Duration::from_secs(1), Some(1)),
+               Duration::from_millis(0));
+}
+
+#[test]
+fn en_passant_needs_a_pawn() {
+    // An en passant square with no pawn in front of it, as a FEN could give
+    let mut game = Pos::from_fen("rnbqkbnr/pppppppp/8/4P3/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1").unwrap();
+    game.en_passant = "d6".parse().ok();
+    game.hash = hash::full_hash(&game);
+    let moves = movegenerator::legal_moves(&game);
+    assert!(moves.iter().all(|m| !m.en_passant));
+
+    let fen = game.to_fen();
+    for m in moves {
+        game.make_move(m);
+        game.unmake_move(m);
+        assert_eq!(game.to_fen(), fen);
+    }
+}
+
 #[test]
 fn pawn_double_start() {
-    let game = Pos::from_fen("8/p7/8/8/8/7p/7P/8 w KQkq - 0 1");
-    let (_, nodes, best_move) = game.negamax_start(4);
-    assert_eq!(best_move, None);
+    let game = Pos::from_fen("k7/p7/8/8/8/7p/7P/K7 w - - 0 1").unwrap();
+    let moves = movegenerator::legal_moves(&game);
+    assert!(moves.iter().all(|m| m.piece.1 != types::PieceType::Pawn));
 }
@@ -0,0 +1,208 @@
+//! Sliding piece attacks from precomputed tables.
+//!
+//! For each square, the blockers that matter are packed into an index into
+//! a table of attack sets. By default the index is found with a magic
+//! multiplication. With the `pext` cargo feature the BMI2 `pext` instruction
+//! is used instead, but only when building for x86_64 with BMI2 enabled,
+//! e.g. with `RUSTFLAGS="-C target-cpu=native"`. Otherwise the feature has
+//! no effect.
+
+use bitboard::BitBoard;
+use square::Square;
+
+/// Lookup data for one square
+#[derive(Clone, Copy, Default)]
+struct Magic {
+    /// Squares whose occupancy changes the attacks. Edges are left out,
+    /// since a piece there does not block anything further.
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    /// Start of the square's attacks in the shared table
+    offset: usize,
+}
+
+impl Magic {
+    #[cfg(not(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2")))]
+    fn index(&self, occupied: u64) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+
+    #[cfg(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2"))]
+    fn index(&self, occupied: u64) -> usize {
+        self.offset + pext(occupied, self.mask) as usize
+    }
+}
+
+#[cfg(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2"))]
+fn pext(a: u64, mask: u64) -> u64 {
+    // Safe, since the build targets CPUs with BMI2
+    unsafe { ::std::arch::x86_64::_pext_u64(a, mask) }
+}
+
+struct SliderTable {
+    magics: [Magic; 64],
+    attacks: Vec<BitBoard>,
+}
+
+impl SliderTable {
+    fn new(directions: [fn(&BitBoard) -> BitBoard; 4]) -> SliderTable {
+        let mut magics = [Magic::default(); 64];
+        let mut attacks = Vec::new();
+
+        for sq in Square::all() {
+            let mut rng = Prng::new(SEEDS[sq.rank()]);
+            let edges = ((BitBoard::new(RANK_1 | RANK_8) & !rank_of(sq)) |
+                         (BitBoard::new(FILE_A | FILE_H) & !file_of(sq))).bits();
+            let mask = slider_attacks(sq, BitBoard::empty(), directions).bits() & !edges;
+            let m = &mut magics[sq.index()];
+            m.mask = mask;
+            m.shift = 64 - mask.count_ones();
+            m.offset = attacks.len();
+
+            // Every subset of the mask, with the attacks it gives
+            let mut occupancies = Vec::new();
+            let mut subset = 0u64;
+            loop {
+                occupancies.push((subset, slider_attacks(sq, BitBoard::new(subset), directions)));
+                subset = subset.wrapping_sub(mask) & mask;
+                if subset == 0 { break; }
+            }
+            attacks.resize(m.offset + occupancies.len(), BitBoard::empty());
+
+            if cfg!(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2")) {
+                for &(occ, atk) in occupancies.iter() {
+                    attacks[m.index(occ)] = atk;
+                }
+                continue;
+            }
+
+            // Try sparse random numbers until one maps all subsets without
+            // harmful collisions
+            let mut used = vec![false; occupancies.len()];
+            loop {
+                m.magic = rng.sparse();
+                if (mask.wrapping_mul(m.magic) >> 56).count_ones() < 6 { continue; }
+
+                for u in used.iter_mut() { *u = false; }
+                let mut ok = true;
+                for &(occ, atk) in occupancies.iter() {
+                    let idx = m.index(occ);
+                    if used[idx - m.offset] && attacks[idx] != atk {
+                        ok = false;
+                        break;
+                    }
+                    used[idx - m.offset] = true;
+                    attacks[idx] = atk;
+                }
+                if ok { break; }
+            }
+        }
+
+        SliderTable { magics, attacks }
+    }
+
+    fn get(&self, sq: Square, occupied: BitBoard) -> BitBoard {
+        self.attacks[self.magics[sq.index()].index(occupied.bits())]
+    }
+}
+
+/// Generator seeds for each rank, known to find magics quickly
+const SEEDS: [u64; 8] = [728, 10316, 55013, 32803, 12281, 15100, 16645, 255];
+
+/// xorshift64* generator, so the magics are the same on every run. The
+/// Zobrist keys come from it too.
+pub(crate) struct Prng(u64);
+
+impl Prng {
+    pub(crate) fn new(seed: u64) -> Prng {
+        Prng(seed)
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(2685821657736338717)
+    }
+
+    /// Random number with few bits set, which makes better magics
+    fn sparse(&mut self) -> u64 {
+        self.next() & self.next() & self.next()
+    }
+}
+
+const RANK_1: u64 = 0x0000_0000_0000_00ff;
+const RANK_8: u64 = 0xff00_0000_0000_0000;
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+const FILE_H: u64 = 0x8080_8080_8080_8080;
+
+fn rank_of(sq: Square) -> BitBoard {
+    BitBoard::new(RANK_1 << (8 * sq.rank()))
+}
+
+fn file_of(sq: Square) -> BitBoard {
+    BitBoard::new(FILE_A << sq.file())
+}
+
+/// Attacks of a slider found by walking each direction until a blocker
+pub fn slider_attacks(sq: Square,
+                      occupied: BitBoard,
+                      directions: [fn(&BitBoard) -> BitBoard; 4])
+                      -> BitBoard {
+    let mut attacks = BitBoard::empty();
+    for f in directions.iter() {
+        let mut to = f(&sq.bb());
+        while to.has_bits() {
+            attacks = attacks | to;
+            if (to & occupied).has_bits() { break; }
+            to = f(&to);
+        }
+    }
+    attacks
+}
+
+pub const BISHOP_DIRECTIONS: [fn(&BitBoard) -> BitBoard; 4] =
+    [BitBoard::nw, BitBoard::ne, BitBoard::sw, BitBoard::se];
+pub const ROOK_DIRECTIONS: [fn(&BitBoard) -> BitBoard; 4] =
+    [BitBoard::up, BitBoard::down, BitBoard::left, BitBoard::right];
+
+lazy_static! {
+    static ref BISHOP_TABLE: SliderTable = SliderTable::new(BISHOP_DIRECTIONS);
+    static ref ROOK_TABLE: SliderTable = SliderTable::new(ROOK_DIRECTIONS);
+}
+
+/// Squares a bishop on `sq` attacks, up to and including the first blocker
+/// in each direction
+pub fn bishop_attacks(sq: Square, occupied: BitBoard) -> BitBoard {
+    BISHOP_TABLE.get(sq, occupied)
+}
+
+/// Squares a rook on `sq` attacks, up to and including the first blocker
+/// in each direction
+pub fn rook_attacks(sq: Square, occupied: BitBoard) -> BitBoard {
+    ROOK_TABLE.get(sq, occupied)
+}
+
+pub fn queen_attacks(sq: Square, occupied: BitBoard) -> BitBoard {
+    bishop_attacks(sq, occupied) | rook_attacks(sq, occupied)
+}
+
+#[test]
+fn magic_attacks() {
+    let mut rng = ::testing::Lcg::new(1);
+    for sq in Square::all() {
+        for _ in 0..200 {
+            let seed = rng.next();
+            let occ = BitBoard::new(seed & (seed >> 7) & (seed << 5)) & !sq.bb();
+            assert_eq!(bishop_attacks(sq, occ), slider_attacks(sq, occ, BISHOP_DIRECTIONS));
+            assert_eq!(rook_attacks(sq, occ), slider_attacks(sq, occ, ROOK_DIRECTIONS));
+        }
+    }
+
+    let d4 = "d4".parse::<Square>().unwrap();
+    let occ = BitBoard::from_str("d6").unwrap() | BitBoard::from_str("f2").unwrap();
+    assert_eq!(rook_attacks(d4, occ).count_bits(), 12);
+    assert_eq!(bishop_attacks(d4, occ).count_bits(), 12);
+    assert_eq!(queen_attacks(d4, BitBoard::empty()).count_bits(), 27);
+}
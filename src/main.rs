@@ -4,6 +4,10 @@ extern crate rook;
 use rook::eval;
 use rook::movegenerator;
 use rook::pos::Pos;
+use rook::pgn;
+use rook::tt::TranspositionTable;
+use rook::search;
+use rook::search::Limits;
 use rook::types::Move;
 use rook::types::Color::*;
 
@@ -12,6 +16,7 @@ use time::Instant;
 
 use std::io;
 use std::io::Write;
+use std::fs::File;
 use std::process;
 
 fn move_from_input(pos: &Pos) -> Move {
@@ -24,7 +29,8 @@ fn move_from_input(pos: &Pos) -> Move {
             println!("\nbye");
             process::exit(0);
         }
-        let mv = Move::from_str(pos, input.trim());
+        let mv = Move::from_str(pos, input.trim())
+            .or_else(|| Move::from_san(pos, input.trim()));
         match mv {
             Some(m) => { return m; },
             None => {
@@ -34,10 +40,45 @@ fn move_from_input(pos: &Pos) -> Move {
     }
 }
 
+/// Write the finished game to game.pgn, with the engine's evaluations as comments
+fn save_pgn(pos: &Pos, evals: &[Option<i64>]) {
+    let mut game = pgn::Game::from_pos(pos);
+    game.set_tag("Event", "rook-cli game");
+    for (pm, ev) in game.moves.iter_mut().zip(evals.iter()) {
+        if let Some(score) = *ev {
+            pm.comments.push(pgn::eval_comment(score));
+        }
+    }
+    match game.to_pgn() {
+        Ok(text) => {
+            if let Ok(mut f) = File::create("game.pgn") {
+                f.write_all(text.as_bytes());
+            }
+        },
+        Err(e) => { println!("could not write PGN: {}", e); }
+    }
+}
+
 fn main() {
     // let yel = ansi_term::Colour::Red;
     // let bold = yel.bold();
-    let mut game = Pos::start();
+    let mut game = match std::env::args().nth(1) {
+        None => Pos::start(),
+        Some(fen) => match Pos::from_fen(&fen) {
+            Ok(p) => {
+                let problems = p.validate();
+                if !problems.is_empty() {
+                    for pr in problems.iter() { println!("illegal position: {}", pr); }
+                    process::exit(1);
+                }
+                p
+            },
+            Err(e) => {
+                println!("{}", e);
+                process::exit(1);
+            }
+        }
+    };
     // let mut game = Pos::from_fen("8/8/8/8/8/8/8/RR4rr w KQkq - 0 1");
     // let mut game = Pos::from_fen("8/8/1PP3k1/8/8/5pp1/1K6/8 w - - 0 1");
     // let mut game = Pos::from_fen("8/8/8/8/8/ppp5/2p5/K7 w KQkq - 0 1");
@@ -53,13 +94,21 @@ fn main() {
 
     let mut totaltime = 0;
     let mut totalnodes = 0;
+    let mut evals = Vec::new();
 
-    //println!("{:?}", hash::HASH);
+    let mut tt = TranspositionTable::new(16);
 
     loop {
         println!("{}     eval: {}\n", game, eval::evaluate(&game));
+        println!("fen: {}\n", game.to_fen());
         //println!("{}", hash::full_hash(&game));
 
+        if let Some(outcome) = game.outcome() {
+            println!("{}  {}", outcome, outcome.result_str());
+            save_pgn(&game, &evals);
+            break;
+        }
+
         if (game.turn == Black) || !human {
             println!("\nthinking... ");
 
@@ -69,7 +118,9 @@ fn main() {
             };
 
             let start = Instant::now();
-            let (_, nodes, best_move) = game.negamax_start(depth);
+            let limits = Limits { depth: Some(depth), ..Limits::default() };
+            let last = search::iterative_deepening(&game, limits, &mut tt, |_| {}).unwrap();
+            let (score, nodes, best_move) = (last.score, last.nodes, last.best_move());
             let end = Instant::now();
             let dur = end - start;
             totaltime += dur.whole_milliseconds();
@@ -86,15 +137,10 @@ fn main() {
                   totaltime as f64 / 1000.0,
                   totalnodes as f64 / totaltime as f64);
 
-            match best_move {
-                Some(mv) => {
-                    game.make_move(mv);
-                }
-                None => {
-                    println!("No moves for {:?}", game.turn);
-                    break;
-                }
-            }
+            let mv = best_move.unwrap();
+            println!("{}. {}{}", game.moves, if game.turn == Black { "... " } else { "" }, mv.to_san(&game));
+            evals.push(Some(if game.turn == White { score } else { -score }));
+            game.make_move(mv);
 
         } else {
             let legals = movegenerator::legal_moves(&game);
@@ -115,6 +161,7 @@ fn main() {
                 if !ok {println!("Move not legal");}
             }
 
+            evals.push(None);
             game.make_move(mv.unwrap());
         }
     }
@@ -1,95 +1,333 @@
 use pos::Pos;
-use types::Move;
-use types::Pc;
+use board::Board;
+use types::{Move, Pc, Color, PieceType, CastlingMove};
 use types::Color::*;
 use types::PieceType::*;
 use bitboard::BitBoard;
+use square::Square;
+use magic;
+use movelist::MoveList;
+
+/// Legal moves, generated directly. Pinned pieces only move along the line
+/// through their king, and in check only moves that capture or block the
+/// checker are tried, or just king moves in double check.
+pub fn legal_moves(pos: &Pos) -> MoveList {
+    let mut moves = MoveList::new();
+    legal_moves_to(pos, !BitBoard::empty(), |_| true, &mut moves);
+    moves
+}
+
+/// Legal captures and promotions, added to `moves`
+pub fn legal_captures(pos: &Pos, moves: &mut MoveList) {
+    let promotion_rank = BitBoard::new(match pos.turn { White => 0xff << 56, Black => 0xff });
+    let ep = pos.en_passant.map_or(BitBoard::empty(), |sq| sq.bb());
+    legal_moves_to(pos, pos.board.theirs(pos.turn) | promotion_rank | ep, is_noisy, moves);
+}
+
+/// Legal moves that neither capture nor promote, added to `moves`
+pub fn legal_quiets(pos: &Pos, moves: &mut MoveList) {
+    legal_moves_to(pos, !pos.board.theirs(pos.turn), |mv| !is_noisy(mv), moves);
+}
 
-pub fn legal_moves(pos: &Pos) -> Vec<Move> {
-    let mut p2 = pos.duplicate();
-    let mut legalmoves = Vec::new();
+/// Captures and promotions change the material on the board
+pub fn is_noisy(mv: &Move) -> bool {
+    mv.capture.is_some() || mv.promotion.is_some()
+}
+
+/// Is the move legal in the position. This is meant for moves from
+/// elsewhere, like a hash move or a killer, which may not fit.
+pub fn is_legal(pos: &Pos, mv: Move) -> bool {
+    let mut moves = MoveList::new();
+    legal_moves_to(pos, mv.to.bb(), |_| true, &mut moves);
+    moves.contains(&mv)
+}
 
-    let allmoves = all_moves(pos);
+/// Legal moves in the order a search wants to try them: the hash move, then
+/// captures with the most valuable victim and least valuable attacker first,
+/// then the killer moves, then the rest. Each stage is generated only when
+/// the previous one runs out, so a search that stops early never pays for
+/// the quiet moves. The position is passed to every call of `next`, so the
+/// search can make and unmake moves in between, but it must be the same one.
+pub struct StagedMoves {
+    hash_move: Option<Move>,
+    killers: [Option<Move>; 2],
+    /// Stop after the captures, as in quiescence search
+    captures_only: bool,
+    stage: Stage,
+    moves: MoveList,
+    index: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    HashMove,
+    GenerateCaptures,
+    Captures,
+    Killer(usize),
+    GenerateQuiets,
+    Quiets,
+    Done,
+}
 
-    for m in allmoves {
-        p2.make_move(m);
-        let (atk, _) = all_moves_and_attack_map(&p2);
-        if (p2.board.pieces[pos.turn as usize + King as usize] & atk).is_empty() {
-            // NO CHECK
-            legalmoves.push(m.clone());
+impl StagedMoves {
+    /// The hash move and killers may come from other positions. They are
+    /// only tried when legal here, and only once.
+    pub fn new(hash_move: Option<Move>, killers: [Option<Move>; 2]) -> StagedMoves {
+        StagedMoves {
+            hash_move,
+            killers,
+            captures_only: false,
+            stage: Stage::HashMove,
+            moves: MoveList::new(),
+            index: 0,
         }
+    }
+
+    /// Just the captures and promotions, in the same order
+    pub fn captures() -> StagedMoves {
+        StagedMoves {
+            captures_only: true,
+            ..StagedMoves::new(None, [None, None])
+        }
+    }
 
-        p2.unmake_move(m);
+    fn already_tried(&self, mv: Move) -> bool {
+        self.hash_move == Some(mv) || self.killers.contains(&Some(mv))
     }
 
-    legalmoves
+    pub fn next(&mut self, pos: &Pos) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    match self.hash_move {
+                        Some(mv) if is_legal(pos, mv) => { return Some(mv); },
+                        _ => { self.hash_move = None; }
+                    }
+                },
+                Stage::GenerateCaptures => {
+                    self.moves.clear();
+                    legal_captures(pos, &mut self.moves);
+                    self.moves.sort_unstable_by_key(|mv| {
+                        let victim = mv.capture.map_or(0, |Pc(_, t)| t as i32 + 1);
+                        let promotion = mv.promotion.map_or(0, |Pc(_, t)| t as i32);
+                        -(victim * 16 + promotion * 8 - mv.piece.1 as i32)
+                    });
+                    self.index = 0;
+                    self.stage = Stage::Captures;
+                },
+                Stage::Captures => {
+                    if self.index == self.moves.len() {
+                        self.stage = if self.captures_only { Stage::Done } else { Stage::Killer(0) };
+                        continue;
+                    }
+                    let mv = self.moves[self.index];
+                    self.index += 1;
+                    if self.hash_move != Some(mv) { return Some(mv); }
+                },
+                Stage::Killer(i) => {
+                    if i == self.killers.len() {
+                        self.stage = Stage::GenerateQuiets;
+                        continue;
+                    }
+                    self.stage = Stage::Killer(i + 1);
+                    match self.killers[i] {
+                        Some(mv) if self.hash_move != Some(mv) && !is_noisy(&mv) &&
+                                    !self.killers[..i].contains(&Some(mv)) &&
+                                    is_legal(pos, mv) => { return Some(mv); },
+                        _ => { self.killers[i] = None; }
+                    }
+                },
+                Stage::GenerateQuiets => {
+                    self.moves.clear();
+                    legal_quiets(pos, &mut self.moves);
+                    self.index = 0;
+                    self.stage = Stage::Quiets;
+                },
+                Stage::Quiets => {
+                    if self.index == self.moves.len() {
+                        self.stage = Stage::Done;
+                        continue;
+                    }
+                    let mv = self.moves[self.index];
+                    self.index += 1;
+                    if !self.already_tried(mv) { return Some(mv); }
+                },
+                Stage::Done => { return None; }
+            }
+        }
+    }
 }
 
-pub fn all_moves(pos: &Pos) -> Vec<Move> {
-    let mut moves = Vec::new();
+/// Legal moves ending on one of the target squares and accepted by `keep`,
+/// added to `moves`
+fn legal_moves_to(pos: &Pos,
+                  targets: BitBoard,
+                  keep: fn(&Move) -> bool,
+                  moves: &mut MoveList) {
+    let b = &pos.board;
+    let us = pos.turn;
+    let them = us.other();
+    let start = moves.len();
+    let king = match b.get_squares(Pc(us, King)).squares().next() {
+        Some(k) => k,
+        None => {
+            // Nothing can be in check
+            pseudo_legal_moves(pos, targets, keep, moves);
+            return;
+        }
+    };
 
-    pawn_moves(pos, &mut moves);
-    knight_moves(pos, &mut moves);
-    bishop_moves(pos, &mut moves);
-    rook_moves(pos, &mut moves);
-    queen_moves(pos, &mut moves);
-    king_moves(pos, &mut moves);
+    let checkers = attackers(b, king, them, b.occupied);
+    let check_mask = match checkers.count_bits() {
+        0 => !BitBoard::empty(),
+        1 => checkers | between(king, checkers.to_square()),
+        _ => BitBoard::empty(),
+    };
+    if (targets & check_mask).has_bits() {
+        pawn_moves(pos, targets & check_mask, moves);
+        knight_moves(pos, targets & check_mask, moves);
+        bishop_moves(pos, targets & check_mask, moves);
+        rook_moves(pos, targets & check_mask, moves);
+        queen_moves(pos, targets & check_mask, moves);
+    } else if pos.en_passant.is_some_and(|sq| (sq.bb() & targets).has_bits()) {
+        pawn_moves(pos, BitBoard::empty(), moves);
+    }
+    king_moves(pos, moves);
+
+    let pinned = pinned(pos, king);
+    let mut i = start;
+    // Like `retain`, but only for the moves added here
+    for j in start..moves.len() {
+        let mv = moves[j];
+        let legal = if (mv.to.bb() & targets).is_empty() || !keep(&mv) {
+            false
+        } else if mv.piece.1 == King {
+            match mv.castling {
+                None => attackers(b, mv.to, them, b.occupied ^ king.bb()).is_empty(),
+                // The path is checked already, but in Chess960 the castling
+                // rook can be what shields the king's target square
+                Some(cst) => {
+                    let rook = pos.castling_rook(cst);
+                    let occupied = (b.occupied & !king.bb() & !rook.bb()) |
+                                   mv.to.bb() | cst.rook_target().bb();
+                    attackers(b, mv.to, them, occupied).is_empty()
+                }
+            }
+        } else if mv.en_passant {
+            // Two pawns leave the rank, which can expose the king
+            let victim = Square::new(mv.to.file(), mv.from.rank());
+            let occupied = (b.occupied ^ mv.from.bb() ^ victim.bb()) | mv.to.bb();
+            (attackers(b, king, them, occupied) & !victim.bb()).is_empty()
+        } else {
+            (pinned & mv.from.bb()).is_empty() || (line(king, mv.from) & mv.to.bb()).has_bits()
+        };
+        if legal {
+            moves[i] = mv;
+            i += 1;
+        }
+    }
+    moves.truncate(i);
+}
 
+pub fn all_moves(pos: &Pos) -> MoveList {
+    let mut moves = MoveList::new();
+    pseudo_legal_moves(pos, !BitBoard::empty(), |_| true, &mut moves);
     moves
 }
 
-pub fn capturing_moves(pos: &Pos) -> Vec<Move> {
-    let mut attacks = all_moves(pos);
-    attacks.retain(|mv| mv.capture.is_some());
-    attacks
-    // all_moves(pos).retain(|mv| mv.capture.is_some())
+/// Moves to the target squares and accepted by `keep`, without checking that
+/// they leave the king safe
+fn pseudo_legal_moves(pos: &Pos,
+                      targets: BitBoard,
+                      keep: fn(&Move) -> bool,
+                      moves: &mut MoveList) {
+    let start = moves.len();
+    pawn_moves(pos, targets, moves);
+    knight_moves(pos, targets, moves);
+    bishop_moves(pos, targets, moves);
+    rook_moves(pos, targets, moves);
+    queen_moves(pos, targets, moves);
+    king_moves(pos, moves);
+
+    let mut i = start;
+    for j in start..moves.len() {
+        if (moves[j].to.bb() & targets).has_bits() && keep(&moves[j]) {
+            moves[i] = moves[j];
+            i += 1;
+        }
+    }
+    moves.truncate(i);
 }
 
-pub fn all_moves_and_attack_map(pos: &Pos) -> (BitBoard, Vec<Move>) {
-    let mut moves = Vec::new();
-    let mut atk = pawn_moves(pos, &mut moves);
-    atk = atk | knight_moves(pos, &mut moves);
-    atk = atk | bishop_moves(pos, &mut moves);
-    atk = atk | rook_moves(pos, &mut moves);
-    atk = atk | queen_moves(pos, &mut moves);
+pub fn all_moves_and_attack_map(pos: &Pos) -> (BitBoard, MoveList) {
+    let mut moves = MoveList::new();
+    let everywhere = !BitBoard::empty();
+    let mut atk = pawn_moves(pos, everywhere, &mut moves);
+    atk = atk | knight_moves(pos, everywhere, &mut moves);
+    atk = atk | bishop_moves(pos, everywhere, &mut moves);
+    atk = atk | rook_moves(pos, everywhere, &mut moves);
+    atk = atk | queen_moves(pos, everywhere, &mut moves);
     atk = atk | king_moves(pos, &mut moves);
 
     (atk, moves)
 }
 
-fn pawn_moves(pos: &Pos, moves: &mut Vec<Move>) -> BitBoard {
+/// Pawn moves to the target squares. En passant is generated whatever the
+/// targets, and the attacked squares are returned.
+fn pawn_moves(pos: &Pos, targets: BitBoard, moves: &mut MoveList) -> BitBoard {
     let mut threatens = BitBoard::empty();
 
     let pawn_positions = pos.board.get_squares(Pc(pos.turn, Pawn));
-    for pp in pawn_positions {
+    for pp in pawn_positions.squares() {
         let (all_moves, all_attacks) = match pos.turn {
             White =>
                 (PAWN_MOVES_WHITE[pp] &
-                 !(BitBoard::new(0x0000_0000_00ff_0000) & !pp & pos.board.occupied).up(),
+                 !(BitBoard::new(0x0000_0000_00ff_0000) & !pp.bb() & pos.board.occupied).up(),
                  PAWN_ATTACKS_WHITE[pp]),
             Black =>
                 (PAWN_MOVES_BLACK[pp] &
-                 !(BitBoard::new(0x0000_ff00_0000_0000) & !pp & pos.board.occupied).down(),
+                 !(BitBoard::new(0x0000_ff00_0000_0000) & !pp.bb() & pos.board.occupied).down(),
                  PAWN_ATTACKS_BLACK[pp])
         };
 
         let possible_moves = all_moves & !pos.board.occupied;
         let possible_attacks = all_attacks & pos.board.theirs(pos.turn);
 
-        for m in possible_moves | possible_attacks {
-            let prom = if (m & BitBoard::new(0xff00_0000_0000_00ff)).has_bits() {
-                Some(Pc(pos.turn, Queen))
-            } else {
-                None
-            };
-            moves.push(Move {
+        for m in ((possible_moves | possible_attacks) & targets).squares() {
+            let mv = Move {
                 from: pp,
                 to: m,
                 piece: Pc(pos.turn, Pawn),
                 capture: pos.board.get(m),
-                promotion: prom,
+                promotion: None,
                 castling: None,
-            });
+                en_passant: false,
+            };
+            if m.rank() == 0 || m.rank() == 7 {
+                for &pt in PROMOTION_TYPES.iter() {
+                    moves.push(Move { promotion: Some(Pc(pos.turn, pt)), ..mv });
+                }
+            } else {
+                moves.push(mv);
+            }
+        }
+
+        if let Some(ep) = pos.en_passant {
+            // The pawn that made the double step has to be there
+            let victim = Square::new(ep.file(), pp.rank());
+            if (all_attacks & ep.bb()).has_bits() &&
+               pos.board.get(victim) == Some(Pc(pos.turn.other(), Pawn)) {
+                moves.push(Move {
+                    from: pp,
+                    to: ep,
+                    piece: Pc(pos.turn, Pawn),
+                    capture: Some(Pc(pos.turn.other(), Pawn)),
+                    promotion: None,
+                    castling: None,
+                    en_passant: true,
+                });
+            }
         }
 
         threatens = threatens | (all_attacks & !pos.board.mine(pos.turn));
@@ -98,14 +336,17 @@ fn pawn_moves(pos: &Pos, moves: &mut Vec<Move>) -> BitBoard {
     threatens
 }
 
-fn knight_moves(pos: &Pos, moves: &mut Vec<Move>) -> BitBoard {
+/// Piece types a pawn can promote to, best first
+const PROMOTION_TYPES: [PieceType; 4] = [Queen, Knight, Rook, Bishop];
+
+fn knight_moves(pos: &Pos, targets: BitBoard, moves: &mut MoveList) -> BitBoard {
     let mut threatens = BitBoard::empty();
     let knight_positions = pos.board.get_squares(Pc(pos.turn, Knight));
-    for kp in knight_positions {
+    for kp in knight_positions.squares() {
 
         let all_moves = KNIGHT_MOVES[kp];
         let possible_mvs = all_moves & !pos.board.mine(pos.turn);
-        for to in possible_mvs {
+        for to in (possible_mvs & targets).squares() {
             moves.push(Move {
                 from: kp,
                 to: to,
@@ -113,6 +354,7 @@ fn knight_moves(pos: &Pos, moves: &mut Vec<Move>) -> BitBoard {
                 capture: pos.board.get(to),
                 promotion: None,
                 castling: None,
+                en_passant: false,
             });
         }
 
@@ -121,15 +363,15 @@ fn knight_moves(pos: &Pos, moves: &mut Vec<Move>) -> BitBoard {
     threatens
 }
 
-fn king_moves(pos: &Pos, moves: &mut Vec<Move>) -> BitBoard {
+fn king_moves(pos: &Pos, moves: &mut MoveList) -> BitBoard {
     let mut threatens = BitBoard::empty();
     let k_positions = pos.board.get_squares(Pc(pos.turn, King));
 
-    for k in k_positions {
+    for k in k_positions.squares() {
         let all_moves = KING_MOVES[k];
         let possible_mvs = all_moves & !pos.board.mine(pos.turn);
 
-        for to in possible_mvs {
+        for to in possible_mvs.squares() {
             moves.push(Move {
                 from: k,
                 to: to,
@@ -137,88 +379,153 @@ fn king_moves(pos: &Pos, moves: &mut Vec<Move>) -> BitBoard {
                 capture: pos.board.get(to),
                 promotion: None,
                 castling: None,
+                en_passant: false,
             });
         }
         threatens = threatens | possible_mvs;
 
-        // castling
-        //
-        // if pos.turn == White {
-        //     let ks =
-        //         pos.castling_rights.contains(WHITE_KINGSIDE) &&
-        //         (pos.board.occupied & castling_pattern(WHITE_KINGSIDE)).is_empty();
-        //     if ks {
-        //         let to = k.right().right();
-        //         moves.push(Move {
-        //             from: k,
-        //             to: to,
-        //             piece: Pc(pos.turn, King),
-        //             capture: pos.board.get(to),
-        //             promotion: None,
-        //             castling: Some(WHITE_KINGSIDE),
-        //         });
-        //     }
-        // }
+        for &cst in CastlingMove::for_color(pos.turn).iter() {
+            if pos.castling_rights & cst as u8 == 0 { continue; }
+
+            let rook = pos.castling_rook(cst);
+            if pos.board.get(rook) != Some(Pc(pos.turn, Rook)) || k.rank() != rook.rank() {
+                continue;
+            }
+            if (pos.board.occupied & cst.empty_pattern(k, rook)).has_bits() { continue; }
+            if cst.king_path(k).squares().any(|sq| is_square_attacked(pos, sq, pos.turn.other())) {
+                continue;
+            }
+
+            moves.push(Move {
+                from: k,
+                to: cst.king_target(),
+                piece: Pc(pos.turn, King),
+                capture: None,
+                promotion: None,
+                castling: Some(cst),
+                en_passant: false,
+            });
+        }
     }
 
     threatens
 }
 
-fn bishop_moves(pos: &Pos, moves: &mut Vec<Move>) -> BitBoard {
-    ray_moves(pos,
-              Pc(pos.turn, Bishop),
-              [BitBoard::nw, BitBoard::ne, BitBoard::sw, BitBoard::se],
-              moves)
+/// Is the player to move in check
+pub fn in_check(pos: &Pos) -> bool {
+    checkers(pos).has_bits()
 }
 
-fn rook_moves(pos: &Pos, moves: &mut Vec<Move>) -> BitBoard {
-    ray_moves(pos,
-              Pc(pos.turn, Rook),
-              [BitBoard::up, BitBoard::down, BitBoard::left, BitBoard::right],
-              moves)
+/// Enemy pieces giving check to the player to move
+pub fn checkers(pos: &Pos) -> BitBoard {
+    let king = pos.board.get_squares(Pc(pos.turn, King));
+    king.squares().fold(BitBoard::empty(), |acc, k| acc | attackers_to(pos, k, pos.turn.other()))
 }
 
-fn queen_moves(pos: &Pos, moves: &mut Vec<Move>) -> BitBoard {
-    ray_moves(pos,
-        Pc(pos.turn, Queen),
-        [BitBoard::nw, BitBoard::ne,   BitBoard::sw,   BitBoard::se],    moves)
-    | ray_moves(pos,
-        Pc(pos.turn, Queen),
-        [BitBoard::up, BitBoard::down, BitBoard::left, BitBoard::right], moves)
+/// Is the square attacked by any piece of the given color
+pub fn is_square_attacked(pos: &Pos, sq: Square, by: Color) -> bool {
+    attackers_to(pos, sq, by).has_bits()
 }
 
-fn ray_moves(pos: &Pos,
-             piece: Pc,
-             direction_func: [fn(&BitBoard) -> BitBoard; 4],
-             moves: &mut Vec<Move>)
-             -> BitBoard {
-    let mut threatens = BitBoard::empty();
-    let diag_pieces = pos.board.get_squares(piece);
-    for dp in diag_pieces {
-        for f in direction_func.iter() {
-            let mut to = f(&dp);
-            while to.has_bits() && (to & pos.board.mine(pos.turn)).is_empty() {
-                threatens = threatens | to;
-                if (to & pos.board.mine(pos.turn)).is_empty() {
-                    let capt = pos.board.get(to);
-                    moves.push(Move {
-                        from: dp,
-                        to: to,
-                        piece: piece,
-                        capture: capt,
-                        promotion: None,
-                        castling: None,
-                    });
-                    if let Some(_) = capt {
-                        break;
-                    }
-                }
+/// Pieces of the given color attacking the square. The square itself may
+/// hold a piece of either color, or none.
+pub fn attackers_to(pos: &Pos, sq: Square, by: Color) -> BitBoard {
+    attackers(&pos.board, sq, by, pos.board.occupied)
+}
 
-                to = f(&to);
-            }
+/// Pieces of the given color attacking the square, with sliders blocked by
+/// `occupied` rather than the board's own occupancy
+fn attackers(b: &Board, sq: Square, by: Color, occupied: BitBoard) -> BitBoard {
+    let pawn_attacks = match by {
+        White => PAWN_ATTACKS_BLACK[sq],
+        Black => PAWN_ATTACKS_WHITE[sq],
+    };
+    let queens = b.get_squares(Pc(by, Queen));
+
+    (pawn_attacks & b.get_squares(Pc(by, Pawn))) |
+    (KNIGHT_MOVES[sq] & b.get_squares(Pc(by, Knight))) |
+    (KING_MOVES[sq] & b.get_squares(Pc(by, King))) |
+    (magic::bishop_attacks(sq, occupied) & (b.get_squares(Pc(by, Bishop)) | queens)) |
+    (magic::rook_attacks(sq, occupied) & (b.get_squares(Pc(by, Rook)) | queens))
+}
+
+/// Our pieces that are the only thing between our king and an enemy slider
+fn pinned(pos: &Pos, king: Square) -> BitBoard {
+    let b = &pos.board;
+    let them = pos.turn.other();
+    let queens = b.get_squares(Pc(them, Queen));
+    let snipers =
+        (magic::rook_attacks(king, b.theirs(pos.turn)) & (b.get_squares(Pc(them, Rook)) | queens)) |
+        (magic::bishop_attacks(king, b.theirs(pos.turn)) & (b.get_squares(Pc(them, Bishop)) | queens));
+
+    let mut pinned = BitBoard::empty();
+    for s in snipers.squares() {
+        let blockers = between(king, s) & b.occupied;
+        if blockers.count_bits() == 1 {
+            pinned = pinned | (blockers & b.mine(pos.turn));
         }
     }
+    pinned
+}
+
+/// Squares strictly between two squares, empty unless they share a rank,
+/// file or diagonal
+fn between(a: Square, b: Square) -> BitBoard {
+    if a.file() == b.file() || a.rank() == b.rank() {
+        magic::rook_attacks(a, b.bb()) & magic::rook_attacks(b, a.bb())
+    } else if a.file_distance(b) == a.rank_distance(b) {
+        magic::bishop_attacks(a, b.bb()) & magic::bishop_attacks(b, a.bb())
+    } else {
+        BitBoard::empty()
+    }
+}
+
+/// The whole rank, file or diagonal through two squares that share one
+fn line(a: Square, b: Square) -> BitBoard {
+    let attacks: fn(Square, BitBoard) -> BitBoard =
+        if a.file() == b.file() || a.rank() == b.rank() {
+            magic::rook_attacks
+        } else {
+            magic::bishop_attacks
+        };
+    (attacks(a, BitBoard::empty()) & attacks(b, BitBoard::empty())) | a.bb() | b.bb()
+}
+
+fn bishop_moves(pos: &Pos, targets: BitBoard, moves: &mut MoveList) -> BitBoard {
+    slider_moves(pos, Bishop, magic::bishop_attacks, targets, moves)
+}
+
+fn rook_moves(pos: &Pos, targets: BitBoard, moves: &mut MoveList) -> BitBoard {
+    slider_moves(pos, Rook, magic::rook_attacks, targets, moves)
+}
 
+fn queen_moves(pos: &Pos, targets: BitBoard, moves: &mut MoveList) -> BitBoard {
+    slider_moves(pos, Queen, magic::queen_attacks, targets, moves)
+}
+
+fn slider_moves(pos: &Pos,
+                pt: PieceType,
+                attacks: fn(Square, BitBoard) -> BitBoard,
+                targets: BitBoard,
+                moves: &mut MoveList)
+                -> BitBoard {
+    let mut threatens = BitBoard::empty();
+    let piece = Pc(pos.turn, pt);
+    for from in pos.board.get_squares(piece).squares() {
+        let possible_mvs = attacks(from, pos.board.occupied) & !pos.board.mine(pos.turn);
+        for to in (possible_mvs & targets).squares() {
+            moves.push(Move {
+                from,
+                to,
+                piece,
+                capture: pos.board.get(to),
+                promotion: None,
+                castling: None,
+                en_passant: false,
+            });
+        }
+        threatens = threatens | possible_mvs;
+    }
     threatens
 }
 
@@ -293,10 +600,10 @@ lazy_static! {
 
 use std::ops::Index;
 
-impl Index<BitBoard> for [BitBoard] {
+impl Index<Square> for [BitBoard] {
     type Output = BitBoard;
 
-    fn index(&self, ib: BitBoard) -> &BitBoard {
-        &self[ib.largest_bit() - 1]
+    fn index(&self, sq: Square) -> &BitBoard {
+        &self[sq.index()]
     }
 }
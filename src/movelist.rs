@@ -0,0 +1,153 @@
+//! Fixed-capacity list of moves that lives on the stack
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+use types::{Move, Pc, Color, PieceType};
+use square::Square;
+
+/// More than the number of moves possible in any position
+pub const MAX_MOVES: usize = 256;
+
+/// A list of at most `MAX_MOVES` moves, which does not allocate. It
+/// dereferences to a slice, so it can be iterated, indexed and sorted like one.
+#[derive(Clone)]
+pub struct MoveList {
+    moves: [Move; MAX_MOVES],
+    len: usize,
+}
+
+const EMPTY: Move = Move {
+    from: Square::from_index(0),
+    to: Square::from_index(0),
+    piece: Pc(Color::White, PieceType::Pawn),
+    capture: None,
+    promotion: None,
+    castling: None,
+    en_passant: false,
+};
+
+impl MoveList {
+    pub fn new() -> MoveList {
+        MoveList { moves: [EMPTY; MAX_MOVES], len: 0 }
+    }
+
+    pub fn push(&mut self, mv: Move) {
+        debug_assert!(self.len < MAX_MOVES);
+        self.moves[self.len] = mv;
+        self.len += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Keep the first `len` moves and drop the rest
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self.len = len;
+        }
+    }
+
+    /// Keep only the moves for which `f` is true, in the same order
+    pub fn retain<F: FnMut(&Move) -> bool>(&mut self, mut f: F) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if f(&self.moves[i]) {
+                self.moves[kept] = self.moves[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> MoveList {
+        MoveList::new()
+    }
+}
+
+impl Deref for MoveList {
+    type Target = [Move];
+
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+}
+
+impl DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut [Move] {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl fmt::Debug for MoveList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = ::std::slice::Iter<'a, Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for MoveList {
+    type Item = Move;
+    type IntoIter = MoveListIntoIter;
+
+    fn into_iter(self) -> MoveListIntoIter {
+        MoveListIntoIter { list: self, index: 0 }
+    }
+}
+
+pub struct MoveListIntoIter {
+    list: MoveList,
+    index: usize,
+}
+
+impl Iterator for MoveListIntoIter {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        if self.index < self.list.len {
+            self.index += 1;
+            Some(self.list.moves[self.index - 1])
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.list.len - self.index;
+        (n, Some(n))
+    }
+}
+
+impl ExactSizeIterator for MoveListIntoIter {}
+
+#[test]
+fn move_list() {
+    use pos::Pos;
+    use movegenerator;
+
+    let mut list = MoveList::new();
+    assert!(list.is_empty());
+    let moves = movegenerator::legal_moves(&Pos::start());
+    for &m in moves.iter() {
+        list.push(m);
+    }
+    assert_eq!(list.len(), 20);
+    assert_eq!(&list[..], &moves[..]);
+
+    list.retain(|m| m.piece.1 == PieceType::Knight);
+    assert_eq!(list.len(), 4);
+    assert!(list.iter().all(|m| m.piece.1 == PieceType::Knight));
+    list.truncate(1);
+    assert_eq!(list.into_iter().collect::<Vec<Move>>(), vec![moves[16]]);
+}
@@ -0,0 +1,641 @@
+//! Reading and writing games in Portable Game Notation
+
+use std::fmt;
+
+use types::Move;
+use types::Color::*;
+use pos::Pos;
+use eval;
+
+/// Tags every PGN game must have, in the order they are written
+const SEVEN_TAG_ROSTER: [&str; 7] =
+    ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+/// Standard starting position
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// A move of the movetext with its annotations
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgnMove {
+    pub mv: Move,
+    /// Numeric annotation glyphs, like 1 for `$1` or `!`
+    pub nags: Vec<u8>,
+    /// Comments before the move, only found at the start of a game or variation
+    pub comments_before: Vec<String>,
+    /// Comments after the move
+    pub comments: Vec<String>,
+    /// Alternatives to this move, each played from the position before it
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(mv: Move) -> PgnMove {
+        PgnMove {
+            mv,
+            nags: Vec::new(),
+            comments_before: Vec::new(),
+            comments: Vec::new(),
+            variations: Vec::new(),
+        }
+    }
+}
+
+/// A game with its tag pairs and movetext
+#[derive(Debug, Clone, PartialEq)]
+pub struct Game {
+    /// Tag pairs in the order they appear
+    pub tags: Vec<(String, String)>,
+    /// Main line of the game
+    pub moves: Vec<PgnMove>,
+    /// Game termination marker: "1-0", "0-1", "1/2-1/2" or "*"
+    pub result: String,
+}
+
+impl Game {
+    /// Value of the tag with the given name
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|t| t.0 == name).map(|t| &t.1[..])
+    }
+
+    /// Position the game starts from, given by the FEN tag or the standard start
+    pub fn start_pos(&self) -> Result<Pos, String> {
+        match self.tag("FEN") {
+            None => Ok(Pos::start()),
+            Some(fen) => Pos::from_fen(fen).map_err(|e| e.to_string())
+        }
+    }
+
+    /// Moves of the main line
+    pub fn mainline(&self) -> Vec<Move> {
+        self.moves.iter().map(|m| m.mv).collect()
+    }
+
+    /// Position after playing the main line, with the moves in its history
+    pub fn end_pos(&self) -> Result<Pos, String> {
+        let mut pos = self.start_pos()?;
+        for pm in self.moves.iter() {
+            pos.make_move(pm.mv);
+        }
+        Ok(pos)
+    }
+
+    /// Build a game from the history of a position. The result is taken from
+    /// `Pos::outcome`, or "*" if the game is still going on.
+    pub fn from_pos(pos: &Pos) -> Game {
+        let mut start = pos.duplicate();
+        while start.undo_move().is_some() {}
+
+        let result = match pos.outcome() {
+            Some(o) => o.result_str(),
+            None => "*"
+        };
+
+        let mut game = Game {
+            tags: Vec::new(),
+            moves: pos.history.iter().map(|&m| PgnMove::new(m)).collect(),
+            result: result.to_string(),
+        };
+        for &name in SEVEN_TAG_ROSTER.iter() {
+            let value = match name {
+                "Date" => "????.??.??",
+                "Result" => result,
+                _ => "?"
+            };
+            game.set_tag(name, value);
+        }
+        let fen = start.to_fen();
+        if fen != START_FEN {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &fen);
+        }
+        game
+    }
+
+    /// Set the value of a tag, adding it if it does not exist yet
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|t| t.0 == name) {
+            Some(t) => { t.1 = value.to_string(); },
+            None => { self.tags.push((name.to_string(), value.to_string())); }
+        }
+    }
+
+    /// Write the game as PGN, with the Seven Tag Roster first and the
+    /// movetext in SAN wrapped to 80 columns
+    pub fn to_pgn(&self) -> Result<String, String> {
+        let mut out = String::new();
+        for &name in SEVEN_TAG_ROSTER.iter() {
+            let value = if name == "Result" { Some(&self.result[..]) } else { self.tag(name) };
+            write_tag(&mut out, name, value.unwrap_or("?"));
+        }
+        for (name, value) in self.tags.iter() {
+            if !SEVEN_TAG_ROSTER.contains(&&name[..]) {
+                write_tag(&mut out, name, value);
+            }
+        }
+        out.push('\n');
+
+        let mut pos = self.start_pos()?;
+        let mut tokens = Vec::new();
+        write_line(&mut pos, &self.moves, &mut tokens);
+        tokens.push(self.result.clone());
+
+        let mut width = 0;
+        for tok in tokens {
+            if width > 0 && width + 1 + tok.len() > 79 {
+                out.push('\n');
+                width = 0;
+            } else if width > 0 {
+                out.push(' ');
+                width += 1;
+            }
+            width += tok.len();
+            out.push_str(&tok);
+        }
+        out.push('\n');
+        Ok(out)
+    }
+}
+
+/// Comment holding a search score, in centipawns from white's point of view,
+/// in the `[%eval 0.35]` format understood by most GUIs. Mate scores are
+/// written as `[%eval #3]`, or `[%eval #-3]` when black mates.
+pub fn eval_comment(score: i64) -> String {
+    if let Some(n) = eval::mate_in_moves(score) {
+        return format!("[%eval #{}]", n);
+    }
+    format!("[%eval {}{}.{:02}]",
+            if score < 0 { "-" } else { "" },
+            score.abs() / 100,
+            score.abs() % 100)
+}
+
+fn write_tag(out: &mut String, name: &str, value: &str) {
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+    out.push_str(&format!("[{} \"{}\"]\n", name, escaped));
+}
+
+fn comment_token(c: &str) -> String {
+    format!("{{{}}}", c.replace('}', ")"))
+}
+
+/// Write the moves of a line as movetext tokens, starting from `pos`.
+/// The position is restored before returning.
+fn write_line(pos: &mut Pos, line: &[PgnMove], tokens: &mut Vec<String>) {
+    let mut need_number = true;
+    for pm in line.iter() {
+        for c in pm.comments_before.iter() {
+            tokens.push(comment_token(c));
+        }
+        if pos.turn == White {
+            tokens.push(format!("{}.", pos.moves));
+        } else if need_number || !pm.comments_before.is_empty() {
+            tokens.push(format!("{}...", pos.moves));
+        }
+        tokens.push(pm.mv.to_san(pos));
+        for n in pm.nags.iter() {
+            tokens.push(format!("${}", n));
+        }
+        for c in pm.comments.iter() {
+            tokens.push(comment_token(c));
+        }
+        for v in pm.variations.iter() {
+            let mut sub = Vec::new();
+            write_line(pos, v, &mut sub);
+            if sub.is_empty() { continue; }
+            sub[0] = format!("({}", sub[0]);
+            let last = sub.len() - 1;
+            sub[last].push(')');
+            tokens.append(&mut sub);
+        }
+        need_number = !pm.comments.is_empty() || !pm.variations.is_empty();
+        pos.make_move(pm.mv);
+    }
+    for _ in 0..line.len() {
+        pos.undo_move();
+    }
+}
+
+/// Error in one game of a PGN file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnError {
+    /// Index of the game in the file, starting from 1
+    pub game: usize,
+    /// Line of the file where the game starts, starting from 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "game {} (line {}): {}", self.game, self.line, self.message)
+    }
+}
+
+/// Read all games of a PGN file. A broken game gives an error in its place,
+/// and reading continues with the next game.
+pub fn read_games(text: &str) -> Vec<Result<Game, PgnError>> {
+    split_games(text)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (line, chunk))| {
+            parse_game(&chunk).map_err(|message| PgnError {
+                game: i + 1,
+                line,
+                message,
+            })
+        })
+        .collect()
+}
+
+/// Read a single game
+pub fn read_game(text: &str) -> Result<Game, PgnError> {
+    match read_games(text).into_iter().next() {
+        Some(g) => g,
+        None => Err(PgnError { game: 1, line: 1, message: "no game found".to_string() })
+    }
+}
+
+/// Split the file into games with the line number each one starts on.
+/// A tag line after some movetext starts a new game.
+fn split_games(text: &str) -> Vec<(usize, String)> {
+    let mut games = Vec::new();
+    let mut current = String::new();
+    let mut start = 1;
+    let mut has_movetext = false;
+
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('%') { continue; }
+        if trimmed.starts_with('[') && has_movetext {
+            games.push((start, current));
+            current = String::new();
+            has_movetext = false;
+        }
+        if current.trim().is_empty() && !trimmed.is_empty() {
+            start = i + 1;
+        }
+        if !trimmed.is_empty() && !trimmed.starts_with('[') {
+            has_movetext = true;
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    if !current.trim().is_empty() {
+        games.push((start, current));
+    }
+    games
+}
+
+fn parse_game(text: &str) -> Result<Game, String> {
+    let mut tags = Vec::new();
+    let mut movetext = String::new();
+    let mut in_tags = true;
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if in_tags && trimmed.starts_with('[') {
+            parse_tags(trimmed, &mut tags)?;
+        } else {
+            if !trimmed.is_empty() { in_tags = false; }
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+
+    let mut game = Game {
+        tags,
+        moves: Vec::new(),
+        result: "*".to_string(),
+    };
+
+    let mut pos = game.start_pos()?;
+    let tokens = tokenize(&movetext)?;
+    let mut it = tokens.into_iter();
+    game.moves = parse_line(&mut pos, &mut it, 0, &mut game.result)?;
+    Ok(game)
+}
+
+/// Parse one or more `[Name "value"]` tag pairs on a line
+fn parse_tags(line: &str, tags: &mut Vec<(String, String)>) -> Result<(), String> {
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) { chars.next(); }
+        match chars.next() {
+            None => { return Ok(()); },
+            Some('[') => {},
+            Some(c) => { return Err(format!("unexpected '{}' in tag line", c)); }
+        }
+
+        let name: String = chars.by_ref()
+            .skip_while(|c| c.is_whitespace())
+            .take_while(|c| !c.is_whitespace())
+            .collect();
+        while chars.peek().is_some_and(|c| c.is_whitespace()) { chars.next(); }
+        if name.is_empty() || chars.next() != Some('"') {
+            return Err(format!("malformed tag '{}'", line));
+        }
+
+        let mut value = String::new();
+        loop {
+            match chars.next() {
+                None => { return Err(format!("unterminated tag value in '{}'", line)); },
+                Some('\\') => {
+                    if let Some(c) = chars.next() { value.push(c); }
+                },
+                Some('"') => { break; },
+                Some(c) => { value.push(c); }
+            }
+        }
+
+        while chars.peek().is_some_and(|c| c.is_whitespace()) { chars.next(); }
+        if chars.next() != Some(']') {
+            return Err(format!("missing ']' in tag '{}'", name));
+        }
+        tags.push((name, value));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    San(String),
+    Nag(u8),
+    Comment(String),
+    Open,
+    Close,
+    Result(String),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {},
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        None => { return Err("unterminated comment".to_string()); },
+                        Some('}') => { break; },
+                        Some(c) => { comment.push(c); }
+                    }
+                }
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            },
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '\n').collect();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            },
+            '(' => { tokens.push(Token::Open); },
+            ')' => { tokens.push(Token::Close); },
+            '$' => {
+                let mut num = String::new();
+                while chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    num.push(chars.next().unwrap());
+                }
+                match num.parse::<u8>() {
+                    Ok(n) => { tokens.push(Token::Nag(n)); },
+                    Err(_) => { return Err(format!("invalid NAG '${}'", num)); }
+                }
+            },
+            _ => {
+                let mut word = c.to_string();
+                while let Some(&n) = chars.peek() {
+                    if n.is_whitespace() || "{}();$".contains(n) { break; }
+                    word.push(n);
+                    chars.next();
+                }
+                push_word(&word, &mut tokens);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Classify a word of movetext: a result, a move number, or a move with
+/// optional `!`/`?` suffix
+fn push_word(word: &str, tokens: &mut Vec<Token>) {
+    match word {
+        "1-0" | "0-1" | "1/2-1/2" | "*" => {
+            tokens.push(Token::Result(word.to_string()));
+            return;
+        },
+        _ => {}
+    }
+
+    // Move numbers like "12." or "12..." may be glued to the move
+    let digits = word.trim_start_matches(|c: char| c.is_ascii_digit());
+    let san = if digits.starts_with('.') { digits.trim_start_matches('.') } else { word };
+    if san.is_empty() { return; }
+
+    let stripped = san.trim_end_matches(['!', '?']);
+    let nag = match &san[stripped.len()..] {
+        "!"  => Some(1),
+        "?"  => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _    => None
+    };
+    tokens.push(Token::San(stripped.to_string()));
+    if let Some(n) = nag {
+        tokens.push(Token::Nag(n));
+    }
+}
+
+/// Parse a line of moves starting from `pos`, recursing into variations.
+/// The position is restored before returning.
+fn parse_line<I: Iterator<Item = Token>>(pos: &mut Pos,
+                                          tokens: &mut I,
+                                          depth: usize,
+                                          result: &mut String)
+                                          -> Result<Vec<PgnMove>, String> {
+    let mut line: Vec<PgnMove> = Vec::new();
+    let mut pending_comments = Vec::new();
+
+    let outcome = loop {
+        let tok = match tokens.next() {
+            None if depth > 0 => { break Err("unterminated variation".to_string()); },
+            None => { break Ok(()); },
+            Some(t) => t
+        };
+        match tok {
+            Token::San(s) => {
+                let mv = match Move::from_san(pos, &s) {
+                    Some(m) => m,
+                    None => { break Err(format!("illegal or ambiguous move '{}'", s)); }
+                };
+                let mut pm = PgnMove::new(mv);
+                pm.comments_before = pending_comments;
+                pending_comments = Vec::new();
+                pos.make_move(mv);
+                line.push(pm);
+            },
+            Token::Nag(n) => {
+                match line.last_mut() {
+                    Some(pm) => { pm.nags.push(n); },
+                    None => { break Err(format!("NAG ${} before any move", n)); }
+                }
+            },
+            Token::Comment(c) => {
+                match line.last_mut() {
+                    Some(pm) => { pm.comments.push(c); },
+                    None => { pending_comments.push(c); }
+                }
+            },
+            Token::Open => {
+                if line.is_empty() {
+                    break Err("variation before any move".to_string());
+                }
+                pos.undo_move();
+                let variation = parse_line(pos, tokens, depth + 1, result);
+                let last = line.last_mut().unwrap();
+                pos.make_move(last.mv);
+                match variation {
+                    Ok(v) => { last.variations.push(v); },
+                    Err(e) => { break Err(e); }
+                }
+            },
+            Token::Close => {
+                if depth > 0 { break Ok(()); }
+                break Err("unmatched ')'".to_string());
+            },
+            Token::Result(r) => {
+                if depth > 0 { break Err("result inside a variation".to_string()); }
+                *result = r;
+                break Ok(());
+            }
+        }
+    };
+
+    if let Some(pm) = line.last_mut() {
+        pm.comments.append(&mut pending_comments);
+    }
+    for _ in 0..line.len() {
+        pos.undo_move();
+    }
+    outcome.map(|_| line)
+}
+
+#[test]
+fn read_simple_game() {
+    let text = r#"[Event "Casual \"blitz\""]
+[Site "?"]
+[White "A"] [Black "B"]
+[Result "1-0"]
+
+{Opening comment} 1. e4 e5 2. Nf3 $1 {develops} Nc6 3. Bc4 Nd4?? (3... Bc5 4. c3 (4. b4 Bxb4) 4... Nf6)
+4. Nxe5!? Qg5 5. Nxf7 Qxg2 6. Rf1 Qxe4+ 7. Be2 Nf3# 0-1
+"#;
+    let game = read_game(text).unwrap();
+    assert_eq!(game.tag("Event"), Some("Casual \"blitz\""));
+    assert_eq!(game.tag("Black"), Some("B"));
+    assert_eq!(game.tags.len(), 5);
+    assert_eq!(game.result, "0-1");
+    assert_eq!(game.moves.len(), 14);
+
+    assert_eq!(game.moves[0].comments_before, vec!["Opening comment".to_string()]);
+    assert_eq!(game.moves[2].nags, vec![1]);
+    assert_eq!(game.moves[2].comments, vec!["develops".to_string()]);
+    assert_eq!(game.moves[5].nags, vec![4]);
+    assert_eq!(game.moves[6].nags, vec![5]);
+
+    let var = &game.moves[5].variations;
+    assert_eq!(var.len(), 1);
+    assert_eq!(var[0].len(), 3);
+    assert_eq!(var[0][0].mv.to_str(), "f8c5");
+    assert_eq!(var[0][1].variations[0][1].mv.to_str(), "c5b4");
+
+    let end = game.end_pos().unwrap();
+    assert_eq!(end.outcome(), Some(::types::Outcome::Checkmate(::types::Color::Black)));
+}
+
+#[test]
+fn read_multiple_games_with_errors() {
+    let text = r#"[Event "One"]
+
+1. e4 e5 1-0
+
+[Event "Two"]
+
+1. e4 e4 2. d4 *
+
+[Event "Three"]
+[FEN "4k3/8/8/8/8/8/8/4K2R w K - 0 1"]
+[SetUp "1"]
+
+1.0-0 Kd7 ; rest of line
+2. Rd1+ 1/2-1/2
+
+[Event "Four"]
+
+1. d4 (1. e4 d5 2. d4 *
+"#;
+    let games = read_games(text);
+    assert_eq!(games.len(), 4);
+    assert_eq!(games[0].as_ref().unwrap().mainline().len(), 2);
+
+    let err = games[1].as_ref().unwrap_err();
+    assert_eq!(err.game, 2);
+    assert_eq!(err.line, 5);
+    assert!(err.message.contains("'e4'"));
+
+    let third = games[2].as_ref().unwrap();
+    assert_eq!(third.result, "1/2-1/2");
+    assert_eq!(third.moves[1].comments, vec!["rest of line".to_string()]);
+    assert_eq!(third.end_pos().unwrap().to_fen(), "8/3k4/8/8/8/8/8/3R2K1 b - - 3 2");
+
+    assert!(games[3].is_err());
+}
+
+#[test]
+fn write_and_read_back() {
+    let text = r#"[Event "Casual \"blitz\""]
+[Site "?"]
+[Date "2024.01.02"]
+[Round "?"]
+[White "A"]
+[Black "B"]
+[Result "0-1"]
+[ECO "C50"]
+
+{Opening comment} 1. e4 e5 2. Nf3 $1 {develops} 2... Nc6 3. Bc4 Nd4 $4 (3...
+Bc5 4. c3 (4. b4 Bxb4) 4... Nf6) 4. Nxe5 $5 Qg5 5. Nxf7 Qxg2 6. Rf1 Qxe4+ 7.
+Be2 Nf3# 0-1
+"#;
+    let game = read_game(text).unwrap();
+    let written = game.to_pgn().unwrap();
+    assert_eq!(written, text);
+    assert_eq!(read_game(&written).unwrap(), game);
+}
+
+#[test]
+fn write_from_pos() {
+    let mut pos = Pos::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+    for s in ["O-O", "Kd7", "Rd1+"].iter() {
+        let mv = Move::from_san(&pos, s).unwrap();
+        pos.make_move(mv);
+    }
+    let mut game = Game::from_pos(&pos);
+    game.set_tag("White", "rook");
+    game.moves[0].comments.push(eval_comment(35));
+    game.moves[1].comments.push(eval_comment(-120));
+
+    assert_eq!(game.to_pgn().unwrap(), r#"[Event "?"]
+[Site "?"]
+[Date "????.??.??"]
+[Round "?"]
+[White "rook"]
+[Black "?"]
+[Result "*"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/8/4K2R w K - 0 1"]
+
+1. O-O {[%eval 0.35]} 1... Kd7 {[%eval -1.20]} 2. Rd1+ *
+"#);
+    assert_eq!(eval_comment(-5), "[%eval -0.05]");
+    assert_eq!(eval_comment(eval::MATE - 5), "[%eval #3]");
+    assert_eq!(eval_comment(2 - eval::MATE), "[%eval #-1]");
+}
@@ -5,13 +5,111 @@ use std::cmp;
 // use std::collections::HashSet;
 
 use bitboard::BitBoard;
-use types::{Pc, Color, Move, CastlingMove};
+use square::Square;
+use types::{Pc, Color, PieceType, Move, CastlingMove, Outcome};
 use types::Color::*;
 use types::PieceType::*;
 use movegenerator;
+use movegenerator::StagedMoves;
 use eval;
+use eval::{MATE, MAX_PLY, INFINITY};
 use board::Board;
-// use hash;
+use hash;
+use tt;
+use tt::{TranspositionTable, Bound};
+use search::{Search, Limits};
+
+/// Field of a FEN string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FenField {
+    Board,
+    Turn,
+    Castling,
+    EnPassant,
+    Halfmoves,
+    Moves,
+}
+
+/// Error from parsing a FEN string. Tells which field is wrong and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FenError {
+    pub field: FenField,
+    pub message: String,
+}
+
+impl FenError {
+    fn new(field: FenField, message: String) -> FenError {
+        FenError { field, message }
+    }
+
+    fn missing(field: FenField) -> FenError {
+        FenError::new(field, "field is missing".to_string())
+    }
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.field {
+            FenField::Board     => "piece placement",
+            FenField::Turn      => "side to move",
+            FenField::Castling  => "castling rights",
+            FenField::EnPassant => "en passant square",
+            FenField::Halfmoves => "halfmove clock",
+            FenField::Moves     => "fullmove number",
+        };
+        write!(f, "invalid FEN {}: {}", name, self.message)
+    }
+}
+
+/// Reason why a position can not occur in a game, from `Pos::validate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// A player does not have exactly one king
+    KingCount(Color, u32),
+    /// A player has more than eight pawns
+    TooManyPawns(Color),
+    /// A pawn on the first or eighth rank
+    PawnOnBackRank(Square),
+    /// The player who just moved is in check
+    OpponentInCheck,
+    /// Castling right without the king on its home square
+    CastlingKingMoved(CastlingMove),
+    /// Castling right without a rook on its home square
+    CastlingRookMoved(CastlingMove),
+    /// The en passant square does not follow a double pawn step
+    InvalidEnPassant(Square),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::KingCount(c, n) =>
+                write!(f, "{:?} has {} kings", c, n),
+            Problem::TooManyPawns(c) =>
+                write!(f, "{:?} has more than 8 pawns", c),
+            Problem::PawnOnBackRank(sq) =>
+                write!(f, "pawn on {}", sq.to_str()),
+            Problem::OpponentInCheck =>
+                write!(f, "the side not to move is in check"),
+            Problem::CastlingKingMoved(cst) =>
+                write!(f, "castling right {} but the king is not on its home square", cst.to_char()),
+            Problem::CastlingRookMoved(cst) =>
+                write!(f, "castling right {} but the rook is not on its home square", cst.to_char()),
+            Problem::InvalidEnPassant(sq) =>
+                write!(f, "no pawn can be captured en passant on {}", sq.to_str()),
+        }
+    }
+}
+
+/// State that can not be recovered from the move alone when unmaking it.
+/// One record is pushed for every move in `history`.
+#[derive(Debug, Clone, Copy)]
+struct Undo {
+    castling_rights: u8,
+    en_passant: Option<Square>,
+    halfmoves: usize,
+    hash: u64,
+}
 
 #[derive(Debug)]
 pub struct Pos {
@@ -19,26 +117,41 @@ pub struct Pos {
     pub turn: Color,
     pub history: Vec<Move>,
     pub castling_rights: u8,
+    /// Rook origin for each castling right, indexed by `CastlingMove::index`
+    castling_rooks: [Square; 4],
+    /// Chess960 mode: castling is read and written in UCI as the king taking
+    /// its own rook
+    pub chess960: bool,
+    /// Square behind a pawn that has just made a double step
+    pub en_passant: Option<Square>,
     pub moves: usize,
     pub halfmoves: usize,
-    pub hash: u64
+    pub hash: u64,
+    undo: Vec<Undo>,
 }
 
 impl Pos {
     pub fn empty() -> Self {
-        Pos {
+        let mut pos = Pos {
             turn: White,
             history: Vec::new(),
             board: Board::empty(),
             moves: 0,
             halfmoves: 0,
             castling_rights: 0b1111,
+            castling_rooks: [Square::from_index(7), Square::from_index(0),
+                             Square::from_index(63), Square::from_index(56)],
+            chess960: false,
+            en_passant: None,
             hash: 0,
-        }
+            undo: Vec::new(),
+        };
+        pos.hash = hash::full_hash(&pos);
+        pos
     }
 
     pub fn start() -> Self {
-        Pos::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+        Pos::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap()
     }
 
     pub fn duplicate(&self) -> Pos {
@@ -50,101 +163,539 @@ impl Pos {
             moves: n.moves,
             halfmoves: n.halfmoves,
             castling_rights: n.castling_rights,
+            castling_rooks: n.castling_rooks,
+            chess960: n.chess960,
+            en_passant: n.en_passant,
             hash: n.hash,
+            undo: n.undo.clone(),
         }
     }
 
-    pub fn from_fen(s: &str) -> Self {
+    /// Parse a position from a FEN string. The halfmove clock and move number
+    /// may be left out, in which case they default to 0 and 1.
+    pub fn from_fen(s: &str) -> Result<Pos, FenError> {
         let mut pos = Pos::empty();
-        let mut parts = s.split(" ");
-        let board = parts.next().unwrap();
-        let turn = parts.next().unwrap();
-        let castling = parts.next().unwrap();
-        let _passant = parts.next().unwrap();
-        let halfmoves = parts.next().unwrap();
-        let moves = parts.next().unwrap();
-
-        let mut row = 7;
-        let mut col = 0;
-        for c in board.chars() {
-            let idx = row * 8 + col;
-            let sq = if col < 8 { BitBoard::from_square(idx) } else { BitBoard::empty() };
-            col += 1;
-            match c {
-                'P' => { pos.board.set(sq, Pc(White, Pawn)); },
-                'R' => { pos.board.set(sq, Pc(White, Rook)); },
-                'N' => { pos.board.set(sq, Pc(White, Knight)); },
-                'B' => { pos.board.set(sq, Pc(White, Bishop)); },
-                'Q' => { pos.board.set(sq, Pc(White, Queen)); },
-                'K' => { pos.board.set(sq, Pc(White, King)); },
-                'p' => { pos.board.set(sq, Pc(Black, Pawn)); },
-                'r' => { pos.board.set(sq, Pc(Black, Rook)); },
-                'n' => { pos.board.set(sq, Pc(Black, Knight)); },
-                'b' => { pos.board.set(sq, Pc(Black, Bishop)); },
-                'q' => { pos.board.set(sq, Pc(Black, Queen)); },
-                'k' => { pos.board.set(sq, Pc(Black, King)); },
-                '/' => { row -= 1; col = 0; },
-                n @ '0' ... '8' => {
-                    let nm = n as u8 - '0' as u8;
-                    col += (nm - 1) as usize;
-                },
-                _ => { col -= 1; }
+        let mut parts = s.split_whitespace();
+        let board = parts.next().ok_or(FenError::missing(FenField::Board))?;
+        let turn = parts.next().ok_or(FenError::missing(FenField::Turn))?;
+        let castling = parts.next().ok_or(FenError::missing(FenField::Castling))?;
+        let passant = parts.next().ok_or(FenError::missing(FenField::EnPassant))?;
+        let halfmoves = parts.next().unwrap_or("0");
+        let moves = parts.next().unwrap_or("1");
+        if let Some(extra) = parts.next() {
+            return Err(FenError::new(FenField::Moves, format!("unexpected trailing '{}'", extra)));
+        }
+
+        let rows: Vec<&str> = board.split('/').collect();
+        if rows.len() != 8 {
+            return Err(FenError::new(FenField::Board,
+                                     format!("expected 8 ranks, found {}", rows.len())));
+        }
+        for (i, row) in rows.iter().enumerate() {
+            let rank = 7 - i;
+            let mut col = 0;
+            for c in row.chars() {
+                if let Some(n) = c.to_digit(10) {
+                    if !(1..=8).contains(&n) {
+                        return Err(FenError::new(FenField::Board,
+                                                 format!("invalid empty square count '{}'", c)));
+                    }
+                    col += n as usize;
+                    continue;
+                }
+                let pt = match PieceType::from_char(c) {
+                    Some(pt) => pt,
+                    None => {
+                        return Err(FenError::new(FenField::Board,
+                                                 format!("unknown piece '{}'", c)));
+                    }
+                };
+                if col < 8 {
+                    let color = if c.is_ascii_uppercase() { White } else { Black };
+                    pos.board.set(Square::new(col, rank), Pc(color, pt));
+                }
+                col += 1;
+            }
+            if col != 8 {
+                return Err(FenError::new(FenField::Board,
+                                         format!("rank {} has {} squares instead of 8", rank + 1, col)));
+            }
+        }
+        for &c in [White, Black].iter() {
+            if pos.board.get_squares(Pc(c, King)).is_empty() {
+                return Err(FenError::new(FenField::Board, format!("no {:?} king", c)));
             }
         }
 
         pos.turn = match turn {
             "w" => White,
             "b" => Black,
-            _ => panic!("invalid fen string")
+            _ => {
+                return Err(FenError::new(FenField::Turn,
+                                         format!("expected 'w' or 'b', found '{}'", turn)));
+            }
         };
 
-        pos.castling_rights = CastlingMove::str_to_flags(castling);
-        pos.halfmoves = halfmoves.parse::<usize>().unwrap();
-        pos.moves = moves.parse::<usize>().unwrap();
-        pos
+        if let Err(msg) = pos.set_castling(castling) {
+            return Err(FenError::new(FenField::Castling, msg));
+        }
+
+        if passant != "-" {
+            // The square behind a pawn of the side not to move
+            let (ep_rank, pawn_rank) = if pos.turn == White { (5, 4) } else { (2, 3) };
+            match passant.parse::<Square>().ok() {
+                Some(sq) if sq.rank() == ep_rank => {
+                    let pawn = Square::new(sq.file(), pawn_rank);
+                    if pos.board.get(pawn) != Some(Pc(pos.turn.other(), Pawn)) {
+                        return Err(FenError::new(FenField::EnPassant,
+                                                 format!("no pawn can be captured en passant on {}", passant)));
+                    }
+                    pos.en_passant = Some(sq);
+                },
+                _ => {
+                    return Err(FenError::new(FenField::EnPassant,
+                                             format!("invalid en passant square '{}'", passant)));
+                }
+            }
+        }
+
+        pos.halfmoves = match halfmoves.parse::<usize>() {
+            Ok(n) => n,
+            Err(_) => {
+                return Err(FenError::new(FenField::Halfmoves,
+                                         format!("'{}' is not a number", halfmoves)));
+            }
+        };
+        pos.moves = match moves.parse::<usize>() {
+            Ok(n) if n > 0 => n,
+            _ => {
+                return Err(FenError::new(FenField::Moves,
+                                         format!("'{}' is not a positive number", moves)));
+            }
+        };
+        pos.hash = hash::full_hash(&pos);
+        Ok(pos)
     }
 
+    /// Start position number `n` of Chess960, from 0 to 959, in Scharnagl's
+    /// numbering. Number 518 is the standard start position.
+    pub fn chess960_start(n: usize) -> Option<Pos> {
+        if n >= 960 { return None; }
+        let mut rank = [None; 8];
+        let mut n = n;
+
+        rank[(n % 4) * 2 + 1] = Some(Bishop);
+        n /= 4;
+        rank[(n % 4) * 2] = Some(Bishop);
+        n /= 4;
+
+        fn put(rank: &mut [Option<PieceType>; 8], nth_empty: usize, pt: PieceType) {
+            let f = (0..8).filter(|&f| rank[f].is_none()).nth(nth_empty).unwrap();
+            rank[f] = Some(pt);
+        }
+        put(&mut rank, n % 6, Queen);
+        n /= 6;
+        let knights = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+        let (n1, n2) = knights[n];
+        put(&mut rank, n2, Knight);
+        put(&mut rank, n1, Knight);
+        put(&mut rank, 0, Rook);
+        put(&mut rank, 0, King);
+        put(&mut rank, 0, Rook);
+
+        let black: String = rank.iter().map(|p| p.unwrap().to_char()).collect();
+        let rooks: String = (0..8).rev().filter(|&f| rank[f] == Some(Rook))
+            .map(|f| (b'a' + f as u8) as char).collect();
+        let fen = format!("{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {}{} - 0 1",
+                          black, black.to_ascii_uppercase(), rooks.to_ascii_uppercase(), rooks);
+        let mut pos = Pos::from_fen(&fen).unwrap();
+        pos.chess960 = true;
+        Some(pos)
+    }
+
+    /// Rook origin for the castling move
+    pub fn castling_rook(&self, cst: CastlingMove) -> Square {
+        self.castling_rooks[cst.index()]
+    }
+
+    /// Set the castling rights from a FEN field in standard, X-FEN or
+    /// Shredder-FEN notation. `K` and `Q` refer to the outermost rook on that
+    /// side of the king, a file letter to the rook on that file. Turns on
+    /// Chess960 mode if the kings or rooks are not on their standard squares.
+    fn set_castling(&mut self, s: &str) -> Result<(), String> {
+        self.castling_rights = 0;
+        if s == "-" { return Ok(()); }
+
+        let mut shredder = false;
+        for c in s.chars() {
+            let color = if c.is_ascii_uppercase() { White } else { Black };
+            let rank = if color == White { 0 } else { 7 };
+            let king_file = self.board.get_squares(Pc(color, King)).squares()
+                .find(|k| k.rank() == rank)
+                .map_or(4, |k| k.file());
+            let rook_files: Vec<usize> = (0..8)
+                .filter(|&f| self.board.get(Square::new(f, rank)) == Some(Pc(color, Rook)))
+                .collect();
+            let [kingside, queenside] = CastlingMove::for_color(color);
+
+            let (cst, file) = match c.to_ascii_lowercase() {
+                'k' => (kingside,
+                        rook_files.iter().cloned().filter(|&f| f > king_file).max().unwrap_or(7)),
+                'q' => (queenside,
+                        rook_files.iter().cloned().filter(|&f| f < king_file).min().unwrap_or(0)),
+                l @ 'a' ..= 'h' => {
+                    shredder = true;
+                    let f = (l as u8 - b'a') as usize;
+                    if f == king_file {
+                        return Err(format!("castling rook '{}' is on the king's file", c));
+                    }
+                    (if f > king_file { kingside } else { queenside }, f)
+                },
+                _ => { return Err(format!("invalid castling rights '{}'", s)); }
+            };
+            if self.castling_rights & cst as u8 != 0 {
+                return Err(format!("invalid castling rights '{}'", s));
+            }
+            self.castling_rights |= cst as u8;
+            self.castling_rooks[cst.index()] = Square::new(file, rank);
+        }
+
+        // Rights without a king and rook to castle with are left alone here
+        self.chess960 = shredder || CastlingMove::all().iter().any(|&cst| {
+            let back_rank = BitBoard::new(if cst.color() == White { 0xff } else { 0xff << 56 });
+            let king = self.board.get_squares(Pc(cst.color(), King)) & back_rank;
+            let rook = self.castling_rook(cst).bb() & self.board.get_squares(Pc(cst.color(), Rook));
+            self.castling_rights & cst as u8 != 0 && king.has_bits() && rook.has_bits() &&
+                (rook != cst.standard_rook().bb() || king != cst.standard_king().bb())
+        });
+        Ok(())
+    }
+
+    /// Castling field of a FEN string. Without `shredder`, rooks are written
+    /// as `K` or `Q` unless another rook is further out on the same side,
+    /// as in X-FEN.
+    fn castling_fen(&self, shredder: bool) -> String {
+        let mut s = String::new();
+        for &cst in CastlingMove::all().iter() {
+            if self.castling_rights & cst as u8 == 0 { continue; }
+            let rook = self.castling_rook(cst);
+            let outside = rook.bb().rank_span(cst.standard_rook().bb()) & !rook.bb();
+            let file = rook.file_char();
+            if !shredder && (outside & self.board.get_squares(Pc(cst.color(), Rook))).is_empty() {
+                s.push(cst.to_char());
+            } else if cst.color() == White {
+                s.push(file.to_ascii_uppercase());
+            } else {
+                s.push(file);
+            }
+        }
+        if s.is_empty() { s.push('-'); }
+        s
+    }
+
+    /// Serialize the position as a FEN string with all six fields. Chess960
+    /// castling rights are written in X-FEN notation.
+    pub fn to_fen(&self) -> String {
+        self.fen(false)
+    }
+
+    /// Serialize the position as a Shredder-FEN string, which writes
+    /// castling rights as the files of the rooks
+    pub fn to_shredder_fen(&self) -> String {
+        self.fen(true)
+    }
+
+    fn fen(&self, shredder: bool) -> String {
+        let mut s = String::new();
+        for row in (0..8).rev() {
+            let mut empty = 0;
+            for col in 0..8 {
+                match self.board.get(Square::new(col, row)) {
+                    None => { empty += 1; },
+                    Some(Pc(c, t)) => {
+                        if empty > 0 {
+                            s.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        let ch = t.to_char();
+                        s.push(if c == White { ch.to_ascii_uppercase() } else { ch });
+                    }
+                }
+            }
+            if empty > 0 { s.push_str(&empty.to_string()); }
+            if row > 0 { s.push('/'); }
+        }
+
+        let ep = match self.en_passant {
+            None => "-".to_string(),
+            Some(sq) => sq.to_str()
+        };
+
+        format!("{} {} {} {} {} {}",
+                s,
+                if self.turn == White { "w" } else { "b" },
+                self.castling_fen(shredder),
+                ep,
+                self.halfmoves,
+                self.moves)
+    }
+
+    /// Play a move. It can be taken back with `undo_move` or `unmake_move`.
     pub fn make_move(&mut self, mv: Move) {
-        debug_assert!(mv.from.count_bits() == 1);
-        debug_assert!(mv.to.count_bits() == 1);
+        self.undo.push(Undo {
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            halfmoves: self.halfmoves,
+            hash: self.hash,
+        });
+
+        let mut h = self.hash ^ hash::side() ^
+                    hash::castling(self.castling_rights) ^
+                    hash::en_passant(self.en_passant_capturable());
 
         if self.turn == Black { self.moves += 1; }
         self.halfmoves += 1;
         self.turn = self.turn.other();
 
-        self.board.clear(mv.from);
-        match mv.promotion {
-            None =>    { self.board.set(mv.to, mv.piece); },
-            Some(p) => { self.board.set(mv.to, p); }
-        }
         match mv.castling {
-            None => {},
+            None => {
+                if mv.en_passant {
+                    let victim = Pos::en_passant_victim(mv);
+                    self.board.remove(victim);
+                    h ^= hash::piece(mv.capture.unwrap(), victim);
+                } else if let Some(capt) = mv.capture {
+                    self.board.remove(mv.to);
+                    h ^= hash::piece(capt, mv.to);
+                }
+                match mv.promotion {
+                    None => { self.board.move_piece(mv.from, mv.to); },
+                    Some(p) => {
+                        self.board.remove(mv.from);
+                        self.board.add(mv.to, p);
+                    }
+                }
+                h ^= hash::piece(mv.piece, mv.from) ^ hash::piece(mv.promotion.unwrap_or(mv.piece), mv.to);
+            },
             Some(cst) => {
-                let (fr, to) = cst.get_rook_move();
-                let p = self.board.get(fr).unwrap();
-                self.board.set(to, p);
-                self.board.clear(fr);
+                // In Chess960 the king and rook may land on each other's origin
+                let rook = self.castling_rook(cst);
+                let p = self.board.remove(rook).unwrap();
+                self.board.move_piece(mv.from, mv.to);
+                self.board.add(cst.rook_target(), p);
+                h ^= hash::piece(p, rook) ^ hash::piece(p, cst.rook_target()) ^
+                     hash::piece(mv.piece, mv.from) ^ hash::piece(mv.piece, mv.to);
             }
         }
-        self.history.push(mv);
 
-        //if let Pc(_, Pawn) = mv.piece { self.halfmoves = 0; }
-        //if let Some(_) = mv.capture { self.halfmoves = 0; }
+        for &cst in CastlingMove::all().iter() {
+            let rook = self.castling_rook(cst);
+            if rook == mv.from || rook == mv.to ||
+               (mv.piece == Pc(cst.color(), King)) {
+                self.castling_rights &= !(cst as u8);
+            }
+        }
 
-        // hash::full_hash(self);
+        self.en_passant = None;
+        if mv.piece.1 == Pawn && mv.from.rank_distance(mv.to) == 2 {
+            self.en_passant = Some(Square::new(mv.from.file(), (mv.from.rank() + mv.to.rank()) / 2));
+        }
+
+        if mv.piece.1 == Pawn || mv.capture.is_some() { self.halfmoves = 0; }
+
+        self.history.push(mv);
+
+        self.hash = h ^ hash::castling(self.castling_rights) ^
+                    hash::en_passant(self.en_passant_capturable());
+        debug_assert_eq!(self.hash, hash::full_hash(self));
     }
 
+    /// Take back the given move, which must be the last one made
     pub fn unmake_move(&mut self, mv: Move) {
-        self.history.pop();
+        debug_assert!(self.history.last() == Some(&mv));
+        self.undo_move();
+    }
+
+    /// Take back the last move made, restoring the position exactly as it
+    /// was before. Returns the move, or `None` if there is no history.
+    pub fn undo_move(&mut self) -> Option<Move> {
+        let mv = match self.history.pop() {
+            None => { return None; },
+            Some(m) => m
+        };
+        let undo = self.undo.pop().unwrap();
+
         self.turn = self.turn.other();
         if self.turn == Black { self.moves -= 1; }
-        self.halfmoves -= 1;
 
-        self.board.clear(mv.to);
-        if mv.capture != None {
-            self.board.set(mv.to, mv.capture.unwrap());
+        self.castling_rights = undo.castling_rights;
+
+        if let Some(cst) = mv.castling {
+            let p = self.board.remove(cst.rook_target()).unwrap();
+            self.board.move_piece(mv.to, mv.from);
+            self.board.add(self.castling_rook(cst), p);
+        } else {
+            self.board.remove(mv.to);
+            self.board.add(mv.from, mv.piece);
+            if let Some(capt) = mv.capture {
+                if mv.en_passant {
+                    self.board.add(Pos::en_passant_victim(mv), capt);
+                } else {
+                    self.board.add(mv.to, capt);
+                }
+            }
+        }
+
+        self.en_passant = undo.en_passant;
+        self.halfmoves = undo.halfmoves;
+        self.hash = undo.hash;
+
+        Some(mv)
+    }
+
+    /// Square of the pawn captured by an en passant move
+    fn en_passant_victim(mv: Move) -> Square {
+        Square::new(mv.to.file(), mv.from.rank())
+    }
+
+    /// Returns how the game has ended, or `None` if it is still going on.
+    /// Draws that a player could claim (threefold repetition and the fifty-move
+    /// rule) are reported as well.
+    pub fn outcome(&self) -> Option<Outcome> {
+        if movegenerator::legal_moves(self).is_empty() {
+            if movegenerator::in_check(self) {
+                return Some(Outcome::Checkmate(self.turn.other()));
+            }
+            return Some(Outcome::Stalemate);
+        }
+
+        if self.insufficient_material() {
+            return Some(Outcome::InsufficientMaterial);
+        }
+
+        let reps = self.repetitions();
+        if reps >= 5 {
+            return Some(Outcome::FivefoldRepetition);
+        }
+        if reps >= 3 {
+            return Some(Outcome::ThreefoldRepetition);
+        }
+
+        if self.halfmoves >= 100 {
+            return Some(Outcome::FiftyMoveRule);
+        }
+
+        None
+    }
+
+    /// Number of times the current position has occurred in the game,
+    /// including the current one
+    pub fn repetitions(&self) -> usize {
+        // Positions before the last capture or pawn move can not repeat, and
+        // the key covers everything else that makes positions the same
+        let plies = cmp::min(self.halfmoves, self.undo.len());
+        1 + (2..=plies).step_by(2)
+            .filter(|&n| self.undo[self.undo.len() - n].hash == self.hash)
+            .count()
+    }
+
+    /// En passant square, if a pawn of the player to move could capture there
+    pub fn en_passant_capturable(&self) -> Option<Square> {
+        let ep = match self.en_passant {
+            None => { return None; },
+            Some(ep) => ep
+        };
+        let attackers = match self.turn {
+            White => ep.bb().sw() | ep.bb().se(),
+            Black => ep.bb().nw() | ep.bb().ne(),
+        };
+        if (attackers & self.board.get_squares(Pc(self.turn, Pawn))).has_bits() {
+            Some(ep)
+        } else {
+            None
         }
-        self.board.set(mv.from, mv.piece);
+    }
+
+    /// Check that the position could occur in a game. Returns the problems
+    /// found, or an empty list if there are none. The search may misbehave
+    /// on positions that do not pass.
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        let b = &self.board;
+
+        for &c in [White, Black].iter() {
+            let kings = b.get_squares(Pc(c, King)).count_bits();
+            if kings != 1 {
+                problems.push(Problem::KingCount(c, kings));
+            }
+            if b.get_squares(Pc(c, Pawn)).count_bits() > 8 {
+                problems.push(Problem::TooManyPawns(c));
+            }
+        }
+
+        let pawns = b.get_squares(Pc(White, Pawn)) | b.get_squares(Pc(Black, Pawn));
+        for sq in (pawns & BitBoard::new(0xff00_0000_0000_00ff)).squares() {
+            problems.push(Problem::PawnOnBackRank(sq));
+        }
+
+        let their_king = b.get_squares(Pc(self.turn.other(), King));
+        if their_king.count_bits() == 1 &&
+           movegenerator::is_square_attacked(self, their_king.to_square(), self.turn) {
+            problems.push(Problem::OpponentInCheck);
+        }
+
+        for &cst in CastlingMove::all().iter() {
+            if self.castling_rights & cst as u8 == 0 { continue; }
+            let back_rank = BitBoard::new(if cst.color() == White { 0xff } else { 0xff << 56 });
+            let king = b.get_squares(Pc(cst.color(), King)) & back_rank;
+            let rook = self.castling_rook(cst);
+
+            let king_home = if self.chess960 {
+                // The king must be between the two rooks
+                king.count_bits() == 1 && (king.to_square() > rook) != cst.is_kingside()
+            } else {
+                king == cst.standard_king().bb()
+            };
+            if !king_home {
+                problems.push(Problem::CastlingKingMoved(cst));
+            }
+            if (b.get_squares(Pc(cst.color(), Rook)) & rook.bb() & back_rank).is_empty() {
+                problems.push(Problem::CastlingRookMoved(cst));
+            }
+        }
+
+        if let Some(ep) = self.en_passant {
+            // The pawn that moved, and the squares it passed over
+            let e = ep.bb();
+            let (pawn, origin, rank) = match self.turn {
+                White => (e.down(), e.up(), 5),
+                Black => (e.up(), e.down(), 2),
+            };
+            if ep.rank() != rank ||
+               (b.get_squares(Pc(self.turn.other(), Pawn)) & pawn).is_empty() ||
+               (b.occupied & (e | origin)).has_bits() {
+                problems.push(Problem::InvalidEnPassant(ep));
+            }
+        }
+
+        problems
+    }
+
+    /// Neither player has enough material left to checkmate
+    fn insufficient_material(&self) -> bool {
+        let b = &self.board;
+        let heavy = b.get_squares(Pc(White, Pawn)) | b.get_squares(Pc(Black, Pawn)) |
+                    b.get_squares(Pc(White, Rook)) | b.get_squares(Pc(Black, Rook)) |
+                    b.get_squares(Pc(White, Queen)) | b.get_squares(Pc(Black, Queen));
+        if heavy.has_bits() { return false; }
+
+        let knights = b.get_squares(Pc(White, Knight)) | b.get_squares(Pc(Black, Knight));
+        let bishops = b.get_squares(Pc(White, Bishop)) | b.get_squares(Pc(Black, Bishop));
+        if (knights | bishops).count_bits() <= 1 { return true; }
+
+        // Any number of bishops, all on squares of the same color
+        let dark_squares = BitBoard::new(0x55aa_55aa_55aa_55aa);
+        knights.is_empty() &&
+            ((bishops & dark_squares).is_empty() || (bishops & !dark_squares).is_empty())
     }
 
     pub fn perft(&mut self, depth: usize) -> usize {
@@ -161,124 +712,185 @@ impl Pos {
         return nodes;
     }
 
-    pub fn negamax_start(&self, depth: usize) -> (i64, usize, Option<Move>) {
+    /// Search to the given depth. Returns the score for the player to move,
+    /// the number of positions visited and the best move, if there is one.
+    pub fn negamax_start(&self, depth: usize, tt: &mut TranspositionTable) -> (i64, usize, Option<Move>) {
         let mut pos = self.duplicate();
-        let mut best_score = i64::min_value();
-        let mut best_move = None;
-        let mut nodes = 0;
-
-        let lmoves = movegenerator::legal_moves(self);
-        for m in lmoves {
-            pos.make_move(m);
-            let (score, n) = pos.negamax_iter(depth - 1);
-            nodes += n;
-            if -score > best_score {
-                best_score = -score;
-                best_move = Some(m);
-            }
-            pos.unmake_move(m);
-        }
-        /*
-        if best_move == None {
-            println!("no legal moves found. all moves:");
-            let moves = movegenerator::all_moves(self);
-            for m in moves {
-                println!("{}", m);
-            }
-        }
-        */
-        (best_score, nodes, best_move)
+        let mut search = Search::new(self, Limits::default(), tt);
+        let (score, nodes) = pos.negamax_iter(cmp::max(depth, 1), 0, -INFINITY, INFINITY, &mut search);
+        let best_move = search.tt.probe(self.hash).and_then(|e| e.best_move.unpack(self));
+        (score, nodes, best_move)
     }
 
-    fn negamax_iter(&mut self, depth: usize) -> (i64, usize) {
+    /// Alpha-beta search with principal variation search: after the first
+    /// move, the others are searched with a zero window just to prove they
+    /// are worse, and searched again with the full window if not. Scores are
+    /// for the player to move, and `ply` counts from the root. If the search
+    /// is stopped, the score is meaningless and nothing is stored.
+    pub fn negamax_iter(&mut self,
+                        depth: usize,
+                        ply: usize,
+                        mut alpha: i64,
+                        beta: i64,
+                        search: &mut Search)
+                        -> (i64, usize) {
         if depth == 0 {
-            // let score = 0;
-            //let (capt, atks) = self.can_capture();
-            if false {
-                // let (score, qnodes) = self.quiescence(1, atks);
-                // println!("Q eval {} {}", qnodes, score);
-                // return (score, qnodes);
-            } else {
-                let score = eval::evaluate(self);
-                if self.turn == White {
-                    return (score, 1);
-                } else {
-                    return (-score, 1);
+            return self.quiescence(ply, alpha, beta, search);
+        }
+        if search.visit() {
+            return (0, 1);
+        }
+
+        let mut hash_move = None;
+        if let Some(e) = search.tt.probe(self.hash) {
+            hash_move = e.best_move.unpack(self);
+            let score = tt::score_from_tt(e.score, ply);
+            if ply > 0 && e.depth as usize >= depth {
+                match e.bound {
+                    Bound::Exact => { return (score, 1); },
+                    Bound::Lower if score >= beta => { return (score, 1); },
+                    Bound::Upper if score <= alpha => { return (score, 1); },
+                    _ => {}
                 }
             }
         }
+        // The previous iteration's line comes first, even if its entries
+        // have been replaced
+        if let Some(m) = search.pv_move(self, ply) {
+            hash_move = Some(m);
+        }
 
-        let mut nodes = 0;
-        let mut best_score = i64::min_value() + 1;
-        let moves = movegenerator::legal_moves(self);
-        for &m in moves.iter() {
+        let alpha_orig = alpha;
+        let mut nodes = 1;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut moves = StagedMoves::new(hash_move, search.killers(ply));
+        while let Some(m) = moves.next(self) {
             self.make_move(m);
-            let (score, n) = self.negamax_iter(depth - 1);
-            nodes += n;
-            if -score > best_score {
-                best_score = -score;
+            let mut score;
+            if best_move.is_none() {
+                let (s, n) = self.negamax_iter(depth - 1, ply + 1, -beta, -alpha, search);
+                score = -s;
+                nodes += n;
+            } else {
+                let (s, n) = self.negamax_iter(depth - 1, ply + 1, -alpha - 1, -alpha, search);
+                score = -s;
+                nodes += n;
+                if score > alpha && score < beta {
+                    let (s, n) = self.negamax_iter(depth - 1, ply + 1, -beta, -alpha, search);
+                    score = -s;
+                    nodes += n;
+                }
             }
             self.unmake_move(m);
+            if search.stopped() {
+                return (0, nodes);
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(m);
+            }
+            if score > alpha { alpha = score; }
+            if alpha >= beta {
+                if !movegenerator::is_noisy(&m) {
+                    search.store_killer(ply, m);
+                }
+                break;
+            }
         }
-        // if moves.len() == 0 {
-            // if depth % 2 == 0 {
-            // println!("{}", self);
-            // for z in movegenerator::all_moves(self) { println!("{}", z); }
-            // println!("CHECKMATE!");
-            // panic!("checkmate or stalemate");
-                // return (-100000, 1);
-            // } else {
-                // return (-1000, 1);
-            // }
-        // }
+
+        if best_move.is_none() {
+            // Checkmate or stalemate
+            return (if movegenerator::in_check(self) { ply as i64 - MATE } else { 0 }, nodes);
+        }
+
+        let bound = if best_score <= alpha_orig {
+            Bound::Upper
+        } else if best_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        search.tt.store(self.hash, depth, tt::score_to_tt(best_score, ply), bound, best_move);
 
         (best_score, nodes)
     }
 
-    fn quiescence(&mut self, depth: usize, atks: Vec<Move>) -> (i64, usize) {
-        if depth == 0 {
-            println!("Q bottomed out");
-            let score = eval::evaluate(self);
-            if self.turn == White {
-                return (score, 1);
-            } else {
-                return (-score, 1);
-            }
+    /// Search captures and promotions until the position is quiet, so that
+    /// the search does not stop in the middle of an exchange. The player to
+    /// move may "stand pat" on the static evaluation instead of capturing,
+    /// except in check, where all evasions are searched. Returns the score
+    /// for the player to move and the number of positions visited. As in
+    /// `negamax_iter`, the score is meaningless if the search is stopped.
+    pub fn quiescence(&mut self,
+                      ply: usize,
+                      mut alpha: i64,
+                      beta: i64,
+                      search: &mut Search)
+                      -> (i64, usize) {
+        if search.visit() {
+            return (0, 1);
+        }
+        let in_check = movegenerator::in_check(self);
+        let stand_pat = if self.turn == White { eval::evaluate(self) } else { -eval::evaluate(self) };
+        if ply >= MAX_PLY {
+            return (stand_pat, 1);
         }
 
-        let s = 0;
-        let n = 0;
-
-        for &a in atks.iter() {
-            println!("\nprev {}", self.history.last().unwrap());
-            self.make_move(a);
-            let (capt, _atks) = self.can_capture();
-            if capt {
-                //s, n = self.quiescence(depth - 1, atks);
-            } else {
-                println!("no more captures, e {}", s);
-                //s, n = self.quiescence(0, atks);
+        let mut nodes = 1;
+        let mut best_score;
+        let mut moves;
+        if in_check {
+            best_score = -INFINITY;
+            moves = StagedMoves::new(None, [None, None]);
+        } else {
+            if stand_pat >= beta {
+                return (stand_pat, nodes);
             }
-            self.unmake_move(a);
-            // println!("Q {} {}", a, s);
-
+            if stand_pat > alpha { alpha = stand_pat; }
+            best_score = stand_pat;
+            moves = StagedMoves::captures();
         }
 
-        (s, n)
-    }
+        while let Some(m) = moves.next(self) {
+            if !in_check {
+                // Delta pruning: skip captures that could not raise alpha even
+                // with a generous positional gain on top of the material
+                let gain = m.capture.map_or(0, |Pc(_, t)| eval::piece_value(t)) +
+                           m.promotion.map_or(0, |Pc(_, t)| eval::piece_value(t) - eval::piece_value(Pawn));
+                if stand_pat + gain + DELTA_MARGIN <= alpha {
+                    best_score = cmp::max(best_score, stand_pat + gain + DELTA_MARGIN);
+                    continue;
+                }
+            }
 
-    fn can_capture(&self) -> (bool, Vec<Move>) {
+            self.make_move(m);
+            let (s, n) = self.quiescence(ply + 1, -beta, -alpha, search);
+            self.unmake_move(m);
+            nodes += n;
+            if search.stopped() {
+                return (0, nodes);
+            }
 
-        let atks = movegenerator::capturing_moves(&self);
-        (!atks.is_empty(), atks)
+            let score = -s;
+            if score > best_score { best_score = score; }
+            if score > alpha { alpha = score; }
+            if alpha >= beta { break; }
+        }
 
-        // let (atk, moves) = movegenerator::all_moves_and_attack_map(&self);
-        // println!("atk map\n{}", (atk & self.board.theirs(self.turn)));
-        // let c = (atk & self.board.theirs(self.turn)).has_bits();
-        // (c, moves)
+        if best_score == -INFINITY {
+            // In check with no evasions
+            return (ply as i64 - MATE, nodes);
+        }
+        (best_score, nodes)
     }
 }
 
+/// More than the static evaluation can change by other than material in one
+/// move, for delta pruning in quiescence search
+const DELTA_MARGIN: i64 = 200;
+
 impl cmp::PartialEq for Pos {
     fn eq(&self, other: &Pos) -> bool {
         self.hash == other.hash
@@ -302,7 +914,10 @@ impl fmt::Display for Pos {
         }
 
         write!(f, "\n\n");
-        write!(f, "       {:?}    {}", self.turn, CastlingMove::flags_to_str(self.castling_rights));
+        write!(f, "       {:?}    {}", self.turn, self.castling_fen(false));
+        if let Some(ep) = self.en_passant {
+            write!(f, "    ep {}", ep.to_str());
+        }
 
         write!(f, "{}\n", self.board);
         //write!(f, "\n}}");
@@ -0,0 +1,218 @@
+//! Standard Algebraic Notation, for example `Nf3`, `exd5`, `O-O` or `e8=Q+`
+
+use types::{Move, Pc, PieceType};
+use types::PieceType::*;
+use square::Square;
+use movegenerator;
+use pos::Pos;
+
+impl Move {
+    /// Format the move in SAN. The move must be legal in the given position.
+    pub fn to_san(&self, pos: &Pos) -> String {
+        let mut s = String::new();
+
+        if let Some(cst) = self.castling {
+            s.push_str(if cst.is_kingside() { "O-O" } else { "O-O-O" });
+        } else {
+            let Pc(_, pt) = self.piece;
+
+            if pt == Pawn {
+                if self.capture.is_some() {
+                    s.push(self.from.file_char());
+                }
+            } else {
+                s.push(pt.to_char().to_ascii_uppercase());
+
+                let others: Vec<Move> = movegenerator::legal_moves(pos).into_iter()
+                    .filter(|m| m.piece == self.piece && m.to == self.to && m.from != self.from)
+                    .collect();
+                if !others.is_empty() {
+                    if others.iter().all(|m| m.from.file() != self.from.file()) {
+                        s.push(self.from.file_char());
+                    } else if others.iter().all(|m| m.from.rank() != self.from.rank()) {
+                        s.push(self.from.rank_char());
+                    } else {
+                        s.push_str(&self.from.to_str());
+                    }
+                }
+            }
+
+            if self.capture.is_some() {
+                s.push('x');
+            }
+            s.push_str(&self.to.to_str());
+
+            if let Some(Pc(_, p)) = self.promotion {
+                s.push('=');
+                s.push(p.to_char().to_ascii_uppercase());
+            }
+        }
+
+        let mut after = pos.duplicate();
+        after.make_move(*self);
+        if movegenerator::in_check(&after) {
+            if movegenerator::legal_moves(&after).is_empty() {
+                s.push('#');
+            } else {
+                s.push('+');
+            }
+        }
+
+        s
+    }
+
+    /// Parse a move in SAN. Common variations are accepted, such as `0-0`,
+    /// `e8Q`, long algebraic `Ng1-f3` and trailing annotations like `!?`.
+    /// Returns `None` if the string does not match exactly one legal move.
+    pub fn from_san(pos: &Pos, s: &str) -> Option<Move> {
+        let mut s = s.trim()
+            .trim_end_matches(['+', '#', '!', '?'])
+            .trim_end_matches("e.p.")
+            .trim_end();
+        if s.is_empty() || !s.is_ascii() { return None; }
+
+        let legal = movegenerator::legal_moves(pos);
+
+        let castling = match s {
+            "O-O" | "0-0" | "o-o" => Some(true),
+            "O-O-O" | "0-0-0" | "o-o-o" => Some(false),
+            _ => None
+        };
+        if let Some(kingside) = castling {
+            return legal.into_iter().find(|m| {
+                m.castling.is_some_and(|c| c.is_kingside() == kingside)
+            });
+        }
+
+        let mut piece = Pawn;
+        if let Some(pt) = s.chars().next().and_then(piece_letter) {
+            piece = pt;
+            s = &s[1..];
+        }
+
+        let mut promotion = None;
+        if piece == Pawn {
+            if let Some(pt) = s.chars().last().and_then(PieceType::from_char) {
+                if s.len() >= 3 && pt != Pawn && pt != King {
+                    promotion = Some(pt);
+                    s = s[..s.len() - 1].trim_end_matches('=');
+                }
+            }
+        }
+
+        let rest: String = s.chars().filter(|&c| c != 'x' && c != ':' && c != '-').collect();
+        if rest.len() < 2 || rest.len() > 4 { return None; }
+        let (prefix, target) = rest.split_at(rest.len() - 2);
+        let to = target.parse::<Square>().ok()?;
+
+        let mut from_file = None;
+        let mut from_rank = None;
+        for c in prefix.chars() {
+            match c {
+                'a' ..= 'h' if from_file.is_none() && from_rank.is_none() => { from_file = Some(c); },
+                '1' ..= '8' if from_rank.is_none() => { from_rank = Some(c); },
+                _ => { return None; }
+            }
+        }
+
+        let mut found = legal.into_iter().filter(|m| {
+            m.piece.1 == piece && m.castling.is_none() && m.to == to &&
+                m.promotion.map(|Pc(_, p)| p) == promotion &&
+                from_file.is_none_or(|f| m.from.file_char() == f) &&
+                from_rank.is_none_or(|r| m.from.rank_char() == r)
+        });
+
+        match (found.next(), found.next()) {
+            (Some(m), None) => Some(m),
+            _ => None
+        }
+    }
+}
+
+/// Uppercase piece letter starting a SAN move
+fn piece_letter(c: char) -> Option<PieceType> {
+    match c {
+        'N' => Some(Knight),
+        'B' => Some(Bishop),
+        'R' => Some(Rook),
+        'Q' => Some(Queen),
+        'K' => Some(King),
+        _   => None
+    }
+}
+
+#[test]
+fn san_output() {
+    let game = Pos::start();
+    let san = |pos: &Pos, s: &str| Move::from_str(pos, s).unwrap().to_san(pos);
+    assert_eq!(san(&game, "g1f3"), "Nf3");
+    assert_eq!(san(&game, "e2e4"), "e4");
+
+    let game = Pos::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+    assert_eq!(san(&game, "e1g1"), "O-O");
+    assert_eq!(san(&game, "e1c1"), "O-O-O");
+    assert_eq!(san(&game, "d5e6"), "dxe6");
+    assert_eq!(san(&game, "e5f7"), "Nxf7");
+    assert_eq!(san(&game, "c3b1"), "Nb1");
+    assert_eq!(san(&game, "e2f1"), "Bf1");
+
+    let game = Pos::from_fen("4k3/1P6/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+    assert_eq!(san(&game, "b7b8q"), "b8=Q+");
+    assert_eq!(san(&game, "b7b8n"), "b8=N");
+    assert_eq!(san(&game, "a1a8"), "Ra8+");
+
+    let game = Pos::from_fen("6rk/6pp/8/4N1N1/8/8/8/6K1 w - - 0 1").unwrap();
+    assert_eq!(san(&game, "g5f7"), "Ngf7#");
+
+    let game = Pos::from_fen("6k1/8/8/8/Q2Q4/8/8/Q3K3 w - - 0 1").unwrap();
+    assert_eq!(san(&game, "a1d1"), "Q1d1");
+    assert_eq!(san(&game, "a4d1"), "Qa4d1");
+    assert_eq!(san(&game, "d4d1"), "Qdd1");
+    assert_eq!(san(&game, "a4b4"), "Qab4");
+    assert_eq!(san(&game, "a1b2"), "Qab2");
+    assert_eq!(san(&game, "d4c3"), "Qdc3");
+
+    let game = Pos::from_fen("r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 2 3").unwrap();
+    assert_eq!(san(&game, "h5f7"), "Qxf7#");
+}
+
+#[test]
+fn san_input() {
+    let game = Pos::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+    let parse = |s: &str| Move::from_san(&game, s).map(|m| m.to_str());
+
+    assert_eq!(parse("O-O"), Some("e1g1".to_string()));
+    assert_eq!(parse("0-0-0"), Some("e1c1".to_string()));
+    assert_eq!(parse("dxe6"), Some("d5e6".to_string()));
+    assert_eq!(parse("de6"), Some("d5e6".to_string()));
+    assert_eq!(parse("Nxf7"), Some("e5f7".to_string()));
+    assert_eq!(parse("Nf7!?"), Some("e5f7".to_string()));
+    assert_eq!(parse("Ne5-f7"), Some("e5f7".to_string()));
+    assert_eq!(parse("Nb1"), Some("c3b1".to_string()));
+    assert_eq!(parse("Ncb1"), Some("c3b1".to_string()));
+    assert_eq!(parse("Nab1"), None);
+    assert_eq!(parse("Qxh3"), Some("f3h3".to_string()));
+    assert_eq!(parse("a3"), Some("a2a3".to_string()));
+    assert_eq!(parse("a5"), None);
+    assert_eq!(parse("Kd1"), Some("e1d1".to_string()));
+    assert_eq!(parse("e2e4"), None);
+    assert_eq!(parse(""), None);
+    assert_eq!(parse("Zz9"), None);
+
+    let game = Pos::from_fen("4k3/1P6/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+    let parse = |s: &str| Move::from_san(&game, s).map(|m| m.to_str());
+    assert_eq!(parse("b8=Q+"), Some("b7b8q".to_string()));
+    assert_eq!(parse("b8N"), Some("b7b8n".to_string()));
+    assert_eq!(parse("b8=r"), Some("b7b8r".to_string()));
+    assert_eq!(parse("b8"), None);
+
+    let game = Pos::from_fen("6k1/8/8/8/Q2Q4/8/8/Q3K3 w - - 0 1").unwrap();
+    let parse = |s: &str| Move::from_san(&game, s).map(|m| m.to_str());
+    assert_eq!(parse("Qd1"), None);
+    assert_eq!(parse("Qad1"), None);
+    assert_eq!(parse("Q4d1"), None);
+    assert_eq!(parse("Qa4d1"), Some("a4d1".to_string()));
+    assert_eq!(parse("Q1d1"), Some("a1d1".to_string()));
+    assert_eq!(parse("Qab4"), Some("a4b4".to_string()));
+    assert_eq!(parse("Qdc3"), Some("d4c3".to_string()));
+}
@@ -0,0 +1,285 @@
+//! Iterative deepening: search to depth 1, 2, 3 and so on until a limit is
+//! reached. Each iteration orders moves by what the previous one found, so
+//! the deeper ones cost less than searching them on their own would.
+
+use std::cmp;
+use std::time::{Duration, Instant};
+
+use pos::Pos;
+use types::Move;
+use tt::TranspositionTable;
+use eval;
+use eval::{MAX_PLY, INFINITY};
+use movegenerator;
+
+/// When to stop searching. With no limits, the search only stops at
+/// `MAX_PLY`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    pub depth: Option<usize>,
+    pub nodes: Option<usize>,
+    /// Time for the whole search
+    pub time: Option<Duration>,
+}
+
+impl Limits {
+    /// Time to spend on a move with `left` on the clock, `increment` added
+    /// after each move and `moves_to_go` moves until the next time control
+    pub fn time_for_move(left: Duration, increment: Duration, moves_to_go: Option<usize>) -> Duration {
+        let moves = cmp::max(moves_to_go.unwrap_or(30), 1) as u32;
+        let time = left / moves + increment * 3 / 4;
+        // Keep something for the time the GUI and the OS take
+        cmp::min(time, left.checked_sub(Duration::from_millis(50)).unwrap_or_default())
+    }
+}
+
+/// Half a pawn either side of the previous iteration's score
+const ASPIRATION_WINDOW: i64 = 50;
+
+/// The time is only checked every this many positions
+const TIME_CHECK_NODES: usize = 1024;
+
+/// State shared by all nodes of a search
+pub struct Search<'a> {
+    pub tt: &'a mut TranspositionTable,
+    limits: Limits,
+    start: Instant,
+    /// Positions visited, over all iterations
+    pub nodes: usize,
+    next_time_check: usize,
+    /// Limits are ignored until the first iteration has finished, so that
+    /// there is always a move to play
+    can_stop: bool,
+    stopped: bool,
+    /// Length of the history at the root
+    root: usize,
+    /// Principal variation of the last finished iteration, tried first
+    pv: Vec<Move>,
+    /// Quiet moves that last caused a beta cutoff at each ply, the most
+    /// recent first. They are likely to refute other moves at the same ply.
+    killers: Vec<[Option<Move>; 2]>,
+}
+
+impl<'a> Search<'a> {
+    pub fn new(root: &Pos, limits: Limits, tt: &'a mut TranspositionTable) -> Search<'a> {
+        Search {
+            tt,
+            limits,
+            start: Instant::now(),
+            nodes: 0,
+            next_time_check: TIME_CHECK_NODES,
+            can_stop: false,
+            stopped: false,
+            root: root.history.len(),
+            pv: Vec::new(),
+            killers: vec![[None, None]; MAX_PLY],
+        }
+    }
+
+    /// Count a position. Returns true if the search has to stop, in which
+    /// case its results are not to be used.
+    pub fn visit(&mut self) -> bool {
+        self.nodes += 1;
+        if !self.can_stop || self.stopped {
+            return self.stopped;
+        }
+        if self.limits.nodes.is_some_and(|n| self.nodes >= n) {
+            self.stopped = true;
+        }
+        if self.nodes >= self.next_time_check {
+            self.next_time_check = self.nodes + TIME_CHECK_NODES;
+            if self.limits.time.is_some_and(|t| self.start.elapsed() >= t) {
+                self.stopped = true;
+            }
+        }
+        self.stopped
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Time since the search started
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// The move the previous iteration played at `ply`, if the moves made
+    /// since the root follow its principal variation
+    pub fn pv_move(&self, pos: &Pos, ply: usize) -> Option<Move> {
+        if ply < self.pv.len() && pos.history[self.root..] == self.pv[..ply] {
+            Some(self.pv[ply])
+        } else {
+            None
+        }
+    }
+
+    pub fn killers(&self, ply: usize) -> [Option<Move>; 2] {
+        self.killers.get(ply).cloned().unwrap_or([None, None])
+    }
+
+    /// Remember a quiet move that caused a beta cutoff
+    pub fn store_killer(&mut self, ply: usize, mv: Move) {
+        if let Some(k) = self.killers.get_mut(ply) {
+            if k[0] != Some(mv) {
+                k[1] = k[0];
+                k[0] = Some(mv);
+            }
+        }
+    }
+
+    /// Worth starting another iteration. It would likely take longer than
+    /// all the previous ones, so not if half the time is gone.
+    fn next_iteration(&self, depth: usize) -> bool {
+        depth <= cmp::min(self.limits.depth.unwrap_or(MAX_PLY), MAX_PLY) &&
+            (!self.can_stop ||
+             self.limits.nodes.is_none_or(|n| self.nodes < n) &&
+             self.limits.time.is_none_or(|t| self.elapsed() < t / 2))
+    }
+}
+
+/// Result of a finished iteration
+#[derive(Clone, Debug)]
+pub struct Iteration {
+    pub depth: usize,
+    /// Score for the player to move
+    pub score: i64,
+    /// Positions visited by the search so far
+    pub nodes: usize,
+    pub time: Duration,
+    /// Permille of the transposition table in use, as in UCI
+    pub hashfull: usize,
+    /// The expected moves from the root, starting with the best one
+    pub pv: Vec<Move>,
+}
+
+impl Iteration {
+    pub fn best_move(&self) -> Option<Move> {
+        self.pv.first().cloned()
+    }
+}
+
+/// Search deeper and deeper until a limit is reached, calling `report`
+/// after each iteration. Entries from earlier searches in `tt` are kept for
+/// ordering but aged. Returns the last finished iteration, which is only
+/// `None` if there are no legal moves.
+pub fn iterative_deepening<F>(root: &Pos, limits: Limits, tt: &mut TranspositionTable, mut report: F)
+                              -> Option<Iteration>
+    where F: FnMut(&Iteration)
+{
+    let mut pos = root.duplicate();
+    tt.new_search();
+    let mut search = Search::new(&pos, limits, tt);
+    let mut last: Option<Iteration> = None;
+
+    let mut depth = 1;
+    while search.next_iteration(depth) {
+        let previous = last.as_ref().map(|it| it.score);
+        let mut delta = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) = match previous {
+            Some(s) if depth >= 4 && eval::mate_in(s).is_none() => (s - delta, s + delta),
+            _ => (-INFINITY, INFINITY),
+        };
+
+        // Search again with a wider window until the score falls inside
+        let score = loop {
+            let (score, _) = pos.negamax_iter(depth, 0, alpha, beta, &mut search);
+            if search.stopped() {
+                break None;
+            }
+            delta *= 2;
+            if score <= alpha {
+                alpha = cmp::max(score - delta, -INFINITY);
+            } else if score >= beta {
+                beta = cmp::min(score + delta, INFINITY);
+            } else {
+                break Some(score);
+            }
+        };
+        let score = match score {
+            Some(s) => s,
+            None => { break; }
+        };
+
+        let pv = principal_variation(&mut pos, search.tt, depth);
+        if pv.is_empty() {
+            break;
+        }
+        let it = Iteration {
+            depth,
+            score,
+            nodes: search.nodes,
+            time: search.elapsed(),
+            hashfull: search.tt.hashfull(),
+            pv,
+        };
+        report(&it);
+        search.pv = it.pv.clone();
+        last = Some(it);
+        search.can_stop = true;
+
+        // A mate within the depth searched is as short as it gets
+        if eval::mate_in(score).is_some_and(|n| n.unsigned_abs() as usize <= depth) {
+            break;
+        }
+        depth += 1;
+    }
+    last
+}
+
+/// The best moves from the position on, followed through the transposition
+/// table for at most `depth` moves
+fn principal_variation(pos: &mut Pos, tt: &TranspositionTable, depth: usize) -> Vec<Move> {
+    let mut pv = Vec::new();
+    while pv.len() < depth {
+        let mv = match tt.probe(pos.hash).and_then(|e| e.best_move.unpack(pos)) {
+            Some(m) if movegenerator::is_legal(pos, m) => m,
+            _ => { break; }
+        };
+        pos.make_move(mv);
+        pv.push(mv);
+    }
+    for _ in 0..pv.len() {
+        pos.undo_move();
+    }
+    pv
+}
+
+#[test]
+fn killers() {
+    let pos = Pos::start();
+    let mut tt = TranspositionTable::new(1);
+    let mut search = Search::new(&pos, Limits::default(), &mut tt);
+    let mv = |s| Move::from_str(&pos, s).unwrap();
+
+    search.store_killer(3, mv("e2e4"));
+    search.store_killer(3, mv("d2d4"));
+    search.store_killer(3, mv("d2d4"));
+    assert_eq!(search.killers(3), [Some(mv("d2d4")), Some(mv("e2e4"))]);
+    assert_eq!(search.killers(2), [None, None]);
+    search.store_killer(MAX_PLY, mv("e2e4"));
+    assert_eq!(search.killers(MAX_PLY), [None, None]);
+
+    // Quiet refutations are found in a search
+    pos.duplicate().negamax_iter(3, 0, -INFINITY, INFINITY, &mut search);
+    let killer = search.killers(1)[0].unwrap();
+    assert!(!movegenerator::is_noisy(&killer));
+}
+
+#[test]
+fn quiescence_limits() {
+    // Kiwipete, full of captures
+    let mut pos = Pos::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+    let mut tt = TranspositionTable::new(1);
+    let mut search = Search::new(&pos, Limits::default(), &mut tt);
+    let (_, nodes) = pos.quiescence(0, -INFINITY, INFINITY, &mut search);
+    assert_eq!(search.nodes, nodes);
+    assert!(nodes > 200);
+
+    let limits = Limits { nodes: Some(100), ..Limits::default() };
+    let mut search = Search::new(&pos, limits, &mut tt);
+    search.can_stop = true;
+    pos.quiescence(0, -INFINITY, INFINITY, &mut search);
+    assert!(search.stopped());
+    assert_eq!(search.nodes, 100);
+}
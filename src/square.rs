@@ -0,0 +1,155 @@
+use std::fmt;
+use std::cmp;
+use std::str::FromStr;
+
+use bitboard::BitBoard;
+
+/// Square on the board, from 0 (a1) to 63 (h8). Files run along bits 0-2 and
+/// ranks along bits 3-5.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug, Hash)]
+pub struct Square(u8);
+
+impl Square {
+    /// Square from file and rank, both counted from 0
+    pub fn new(file: usize, rank: usize) -> Square {
+        debug_assert!(file < 8 && rank < 8);
+        Square((rank * 8 + file) as u8)
+    }
+
+    pub const fn from_index(i: usize) -> Square {
+        debug_assert!(i < 64);
+        Square(i as u8)
+    }
+
+    /// All squares from a1 to h8
+    pub fn all() -> impl Iterator<Item = Square> {
+        (0..64).map(Square)
+    }
+
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+
+    /// File from 0 (a) to 7 (h)
+    pub fn file(&self) -> usize {
+        (self.0 & 7) as usize
+    }
+
+    /// Rank from 0 (first) to 7 (eighth)
+    pub fn rank(&self) -> usize {
+        (self.0 >> 3) as usize
+    }
+
+    pub fn file_char(&self) -> char {
+        (b'a' + self.file() as u8) as char
+    }
+
+    pub fn rank_char(&self) -> char {
+        (b'1' + self.rank() as u8) as char
+    }
+
+    /// The same square seen from the other side of the board, e2 becomes e7
+    pub fn mirror(&self) -> Square {
+        Square(self.0 ^ 56)
+    }
+
+    /// The same square reflected between the a and h files, e2 becomes d2
+    pub fn flip_file(&self) -> Square {
+        Square(self.0 ^ 7)
+    }
+
+    /// Number of king moves between the squares
+    pub fn distance(&self, other: Square) -> usize {
+        cmp::max(file_diff(*self, other), rank_diff(*self, other))
+    }
+
+    /// Difference between the files of the squares
+    pub fn file_distance(&self, other: Square) -> usize {
+        file_diff(*self, other)
+    }
+
+    /// Difference between the ranks of the squares
+    pub fn rank_distance(&self, other: Square) -> usize {
+        rank_diff(*self, other)
+    }
+
+    /// Sum of the file and rank differences
+    pub fn manhattan_distance(&self, other: Square) -> usize {
+        file_diff(*self, other) + rank_diff(*self, other)
+    }
+
+    pub fn bb(&self) -> BitBoard {
+        BitBoard::from_square(self.index())
+    }
+
+    pub fn to_str(&self) -> String {
+        let mut s = String::new();
+        s.push(self.file_char());
+        s.push(self.rank_char());
+        s
+    }
+}
+
+impl FromStr for Square {
+    type Err = String;
+
+    /// Parse a square in algebraic notation, like `e4`
+    fn from_str(s: &str) -> Result<Square, String> {
+        let b = s.as_bytes();
+        if b.len() != 2 { return Err(format!("invalid square '{}'", s)); }
+        let (cc, rc) = (b[0], b[1]);
+        if (b'a'..=b'h').contains(&cc) && (b'1'..=b'8').contains(&rc) {
+            Ok(Square::new((cc - b'a') as usize, (rc - b'1') as usize))
+        } else {
+            Err(format!("invalid square '{}'", s))
+        }
+    }
+}
+
+fn file_diff(a: Square, b: Square) -> usize {
+    (a.file() as isize - b.file() as isize).unsigned_abs()
+}
+
+fn rank_diff(a: Square, b: Square) -> usize {
+    (a.rank() as isize - b.rank() as isize).unsigned_abs()
+}
+
+impl From<Square> for BitBoard {
+    fn from(sq: Square) -> BitBoard {
+        sq.bb()
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.file_char(), self.rank_char())
+    }
+}
+
+#[test]
+fn square_coordinates() {
+    let e4 = "e4".parse::<Square>().unwrap();
+    assert_eq!((e4.file(), e4.rank(), e4.index()), (4, 3, 28));
+    assert_eq!(e4, Square::new(4, 3));
+    assert_eq!(e4.to_str(), "e4");
+    assert_eq!(e4.to_string(), "e4");
+    assert_eq!(e4.mirror().to_str(), "e5");
+    assert_eq!(e4.flip_file().to_str(), "d4");
+    assert_eq!("a1".parse::<Square>().unwrap().mirror().to_str(), "a8");
+    assert_eq!("i1".parse::<Square>().ok(), None);
+    assert_eq!("a9".parse::<Square>().ok(), None);
+    assert_eq!("e".parse::<Square>().ok(), None);
+
+    let a1 = Square::from_index(0);
+    let h8 = Square::from_index(63);
+    assert_eq!(a1.distance(h8), 7);
+    assert_eq!(a1.manhattan_distance(h8), 14);
+    assert_eq!(e4.distance(Square::new(5, 5)), 2);
+    assert_eq!(e4.distance(e4), 0);
+
+    assert_eq!(BitBoard::from(e4), BitBoard::from_square(28));
+    assert_eq!(e4.bb().to_square(), e4);
+    let squares: Vec<Square> = (a1.bb() | e4.bb() | h8.bb()).squares().collect();
+    assert_eq!(squares, vec![a1, e4, h8]);
+    assert_eq!(Square::all().count(), 64);
+}
@@ -0,0 +1,198 @@
+//! Transposition table: search results stored by position key, so a
+//! position reached again by another move order is not searched twice.
+
+use std::mem;
+
+use types::{Move, PackedMove};
+use eval;
+
+/// How the stored score relates to the true score of the position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is exact
+    Exact,
+    /// The true score is at least the stored one (the search failed high)
+    Lower,
+    /// The true score is at most the stored one (the search failed low)
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Entry {
+    /// Top bits of the key, to tell positions sharing a bucket apart
+    check: u16,
+    pub best_move: PackedMove,
+    pub depth: u8,
+    pub bound: Bound,
+    /// Search the entry was last written in
+    age: u8,
+    pub score: i64,
+}
+
+/// Entries that share a slot in the table. A new position may replace any
+/// of them.
+const BUCKET: usize = 4;
+
+pub struct TranspositionTable {
+    buckets: Vec<[Option<Entry>; BUCKET]>,
+    age: u8,
+}
+
+impl TranspositionTable {
+    /// Table taking about `mb` megabytes
+    pub fn new(mb: usize) -> TranspositionTable {
+        let mut tt = TranspositionTable { buckets: Vec::new(), age: 0 };
+        tt.resize(mb);
+        tt
+    }
+
+    /// Change the size, which clears the table
+    pub fn resize(&mut self, mb: usize) {
+        let n = mb * 1024 * 1024 / mem::size_of::<[Option<Entry>; BUCKET]>();
+        self.buckets = vec![[None; BUCKET]; n.max(1)];
+        self.age = 0;
+    }
+
+    pub fn clear(&mut self) {
+        for b in self.buckets.iter_mut() {
+            *b = [None; BUCKET];
+        }
+        self.age = 0;
+    }
+
+    /// Start a new search. Entries from earlier ones are replaced first.
+    pub fn new_search(&mut self) {
+        self.age = self.age.wrapping_add(1);
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        let check = (key >> 48) as u16;
+        self.buckets[self.index(key)].iter()
+            .filter_map(|&e| e)
+            .find(|e| e.check == check)
+    }
+
+    /// Store a search result. An earlier entry for the position is kept if
+    /// it is from this search, deeper, and the new score is only a bound.
+    /// Otherwise the oldest and shallowest entry in the bucket is replaced.
+    pub fn store(&mut self, key: u64, depth: usize, score: i64, bound: Bound, best_move: Option<Move>) {
+        let check = (key >> 48) as u16;
+        let age = self.age;
+        let i = self.index(key);
+        let bucket = &mut self.buckets[i];
+
+        let same = bucket.iter().position(|e| e.is_some_and(|e| e.check == check));
+        let slot = match same {
+            Some(s) => {
+                let old = bucket[s].unwrap();
+                if bound != Bound::Exact && old.age == age && old.depth as usize > depth {
+                    return;
+                }
+                s
+            },
+            None => {
+                // Entries from older searches count as 8 plies shallower
+                (0..BUCKET).min_by_key(|&s| match bucket[s] {
+                    None => i32::MIN,
+                    Some(e) => e.depth as i32 - 8 * age.wrapping_sub(e.age) as i32,
+                }).unwrap()
+            }
+        };
+
+        let best_move = match (best_move, same) {
+            (Some(m), _) => m.pack(),
+            // Keep the move from an earlier search of the position
+            (None, Some(_)) => bucket[slot].unwrap().best_move,
+            (None, None) => PackedMove::null(),
+        };
+        bucket[slot] = Some(Entry {
+            check,
+            best_move,
+            depth: depth.min(u8::MAX as usize) as u8,
+            bound,
+            age,
+            score,
+        });
+    }
+
+    /// Permille of the table filled by the current search, estimated from
+    /// its start, as reported in the UCI `hashfull` info
+    pub fn hashfull(&self) -> usize {
+        let n = self.buckets.len().min(1000 / BUCKET);
+        let used = self.buckets[..n].iter()
+            .flat_map(|b| b.iter())
+            .filter(|e| e.is_some_and(|e| e.age == self.age))
+            .count();
+        used * 1000 / (n * BUCKET)
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.buckets.len() as u64) as usize
+    }
+}
+
+/// Mate scores count plies from the root, but an entry may be found at
+/// another ply. They are stored counting from the entry's position instead.
+pub fn score_to_tt(score: i64, ply: usize) -> i64 {
+    match eval::mate_in(score) {
+        Some(_) if score > 0 => score + ply as i64,
+        Some(_) => score - ply as i64,
+        None => score,
+    }
+}
+
+/// Undo `score_to_tt` for an entry found `ply` plies from the root
+pub fn score_from_tt(score: i64, ply: usize) -> i64 {
+    match eval::mate_in(score) {
+        Some(_) if score > 0 => score - ply as i64,
+        Some(_) => score + ply as i64,
+        None => score,
+    }
+}
+
+#[test]
+fn store_and_probe() {
+    use pos::Pos;
+    use movegenerator;
+
+    let mut tt = TranspositionTable::new(1);
+    assert_eq!(mem::size_of::<Option<Entry>>(), 16);
+    assert_eq!(tt.buckets.len(), 1024 * 1024 / 64);
+
+    let pos = Pos::start();
+    let mv = movegenerator::legal_moves(&pos)[0];
+    assert!(tt.probe(pos.hash).is_none());
+    tt.store(pos.hash, 3, 25, Bound::Lower, Some(mv));
+    let e = tt.probe(pos.hash).unwrap();
+    assert_eq!((e.depth, e.score, e.bound), (3, 25, Bound::Lower));
+    assert_eq!(e.best_move.unpack(&pos), Some(mv));
+
+    // A shallower bound does not replace a deeper result, an exact score does
+    tt.store(pos.hash, 2, 10, Bound::Upper, None);
+    assert_eq!(tt.probe(pos.hash).unwrap().score, 25);
+    tt.store(pos.hash, 1, 10, Bound::Exact, None);
+    let e = tt.probe(pos.hash).unwrap();
+    assert_eq!((e.depth, e.score), (1, 10));
+    assert_eq!(e.best_move.unpack(&pos), Some(mv));
+
+    // Keys in the same bucket fill it, then the shallowest entry goes
+    let key = |i: u64| (i << 48) | 7;
+    assert_eq!(tt.index(key(1)), tt.index(key(2)));
+    for i in 1..5 {
+        tt.store(key(i), i as usize + 1, 0, Bound::Exact, None);
+    }
+    tt.store(key(5), 9, 0, Bound::Exact, None);
+    assert!(tt.probe(key(1)).is_none());
+    assert!((2..6).all(|i| tt.probe(key(i)).is_some()));
+
+    // After a new search, old entries go before shallower new ones
+    tt.new_search();
+    tt.store(key(6), 1, 0, Bound::Exact, None);
+    tt.store(key(7), 1, 0, Bound::Exact, None);
+    assert!(tt.probe(key(6)).is_some() && tt.probe(key(7)).is_some());
+
+    assert!(tt.hashfull() > 0);
+    tt.clear();
+    assert!(tt.probe(key(5)).is_none());
+    assert_eq!(tt.hashfull(), 0);
+}
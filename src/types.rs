@@ -5,7 +5,9 @@ use std::fmt;
 use self::PieceType::*;
 use self::Color::*;
 use bitboard::BitBoard;
+use square::Square;
 use pos::Pos;
+use movegenerator;
 
 
 /// Player color
@@ -36,6 +38,33 @@ pub enum PieceType {
     King = 5,
 }
 
+impl PieceType {
+    /// Parse a piece type from its letter, for example 'n' or 'N' for a knight
+    pub fn from_char(c: char) -> Option<PieceType> {
+        match c.to_ascii_lowercase() {
+            'p' => Some(Pawn),
+            'n' => Some(Knight),
+            'b' => Some(Bishop),
+            'r' => Some(Rook),
+            'q' => Some(Queen),
+            'k' => Some(King),
+            _   => None
+        }
+    }
+
+    /// Lowercase letter of the piece type
+    pub fn to_char(self) -> char {
+        match self {
+            Pawn   => 'p',
+            Knight => 'n',
+            Bishop => 'b',
+            Rook   => 'r',
+            Queen  => 'q',
+            King   => 'k',
+        }
+    }
+}
+
 /// Chess piece. Consists of color and piece type.
 #[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug)]
 pub struct Pc(pub Color, pub PieceType);
@@ -53,13 +82,62 @@ impl fmt::Display for Pc {
     }
 }
 
+/// How a game has ended
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Outcome {
+    /// The given player has checkmated the opponent
+    Checkmate(Color),
+    /// The player to move has no legal moves but is not in check
+    Stalemate,
+    /// Neither player can possibly checkmate
+    InsufficientMaterial,
+    /// The same position has occurred five times
+    FivefoldRepetition,
+    /// The same position has occurred three times
+    ThreefoldRepetition,
+    /// Fifty moves by each player without a capture or a pawn move
+    FiftyMoveRule,
+}
+
+impl Outcome {
+    /// Winner of the game, `None` for a draw
+    pub fn winner(&self) -> Option<Color> {
+        match *self {
+            Outcome::Checkmate(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    /// Result in the usual notation: "1-0", "0-1" or "1/2-1/2"
+    pub fn result_str(&self) -> &'static str {
+        match self.winner() {
+            Some(White) => "1-0",
+            Some(Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Outcome::Checkmate(c)         => write!(f, "{:?} wins by checkmate", c),
+            Outcome::Stalemate            => write!(f, "Draw by stalemate"),
+            Outcome::InsufficientMaterial => write!(f, "Draw by insufficient material"),
+            Outcome::FivefoldRepetition   => write!(f, "Draw by fivefold repetition"),
+            Outcome::ThreefoldRepetition  => write!(f, "Draw by threefold repetition"),
+            Outcome::FiftyMoveRule        => write!(f, "Draw by the fifty-move rule"),
+        }
+    }
+}
+
 /// Chess move
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub struct Move {
     /// Origin square
-    pub from: BitBoard,
+    pub from: Square,
     /// Target square
-    pub to: BitBoard,
+    pub to: Square,
     /// Piece
     pub piece: Pc,
     /// If this is a capturing move, contains the captured piece.
@@ -68,22 +146,26 @@ pub struct Move {
     pub promotion: Option<Pc>,
     /// If this a castling, contain the type
     pub castling: Option<CastlingMove>,
+    /// Is this an en passant capture
+    pub en_passant: bool,
 }
 
 impl Move {
     /// Parse a move from string.
-    /// For example, 'e2e4' for a simple pawn move or 'e7e8q' for promotion to queen
+    /// For example, 'e2e4' for a simple pawn move or 'e7e8q' for promotion to queen.
+    /// Returns `None` if the string is not a legal move in the position.
     pub fn from_str(pos: &Pos, s: &str) -> Option<Move> {
-        let fr_str: String = s.chars().take(2).collect();
-        let to_str: String = s.chars().skip(2).take(2).collect();
-        let pr_in = s.chars().skip(4).next();
+        if s.len() < 4 || s.len() > 5 || !s.is_ascii() { return None; }
+        let fr_str = &s[0..2];
+        let to_str = &s[2..4];
+        let pr_in = s.chars().nth(4);
 
-        let fr = match BitBoard::from_str(&fr_str) {
+        let fr = match fr_str.parse::<Square>().ok() {
             None => { return None; },
             Some(sq) => sq
         };
 
-        let to = match BitBoard::from_str(&to_str) {
+        let to = match to_str.parse::<Square>().ok() {
             None => { return None; },
             Some(sq) => sq
         };
@@ -95,51 +177,68 @@ impl Move {
 
         let Pc(color, _) = pc;
 
-        let pr_type = match pr_in {
-            None => None,
-            Some(prs) => {
-                let pt = match prs {
-                    'q' => Queen,
-                    'r' => Rook,
-                    'k' => Knight,
-                    'b' => Bishop,
-                    _ => panic!("invalid promotion type")
-                };
-                Some(Pc(color, pt))
+        let promotes = pc.1 == Pawn && (to.rank() == 0 || to.rank() == 7);
+        let pr_type = match (pr_in, promotes) {
+            (None, false) => None,
+            (Some(prs), true) => {
+                match PieceType::from_char(prs) {
+                    Some(Pawn) | Some(King) | None => { return None; },
+                    Some(pt) => Some(Pc(color, pt))
+                }
             }
+            _ => { return None; }
         };
 
         let mut castling = None;
+        let mut to = to;
         if let Pc(_, King) = pc {
-             if let Some(cst) = CastlingMove::from_squares(fr | to) {
-                castling = Some(cst);
+            if pos.chess960 {
+                // King takes own rook
+                castling = CastlingMove::for_color(color).iter().cloned()
+                    .find(|&c| pos.castling_rights & c as u8 != 0 && pos.castling_rook(c) == to);
+                if let Some(cst) = castling { to = cst.king_target(); }
+            } else {
+                castling = CastlingMove::from_squares(fr, to);
             }
         }
 
-        Some(Move {
+        let en_passant = pc.1 == Pawn && pos.en_passant == Some(to);
+        let capture = if en_passant {
+            Some(Pc(color.other(), Pawn))
+        } else if castling.is_some() {
+            None
+        } else {
+            pos.board.get(to)
+        };
+
+        let mv = Move {
             from: fr,
             to: to,
             piece: pc,
-            capture: pos.board.get(to),
+            capture: capture,
             promotion: pr_type,
             castling: castling,
-        })
+            en_passant: en_passant,
+        };
+        if movegenerator::is_legal(pos, mv) { Some(mv) } else { None }
+    }
+
+    /// Format the move for UCI in the given position. In Chess960 mode
+    /// castling is written as the king taking its own rook, like `e1h1`.
+    pub fn to_uci(&self, pos: &Pos) -> String {
+        match self.castling {
+            Some(cst) if pos.chess960 =>
+                format!("{}{}", self.from.to_str(), pos.castling_rook(cst).to_str()),
+            _ => self.to_str()
+        }
     }
 
     pub fn to_str(&self) -> String {
-        let promo_str = match self.promotion {
-            None => "",
-            Some(Pc(_, p)) => {
-                match p {
-                    Queen => "q",
-                    Rook => "r",
-                    Knight => "n",
-                    Bishop => "b",
-                    _ => panic!("invalid promotion")
-                }
-            }
-        };
-        format!("{}{}{}", self.from.to_str(), self.to.to_str(), promo_str)
+        let mut s = format!("{}{}", self.from.to_str(), self.to.to_str());
+        if let Some(Pc(_, p)) = self.promotion {
+            s.push(p.to_char());
+        }
+        s
     }
 }
 
@@ -172,6 +271,157 @@ impl fmt::Display for Move {
     }
 }
 
+/// Move packed into 16 bits: origin square in bits 0-5, target square in
+/// bits 6-11 and a flags nibble on top. Used where moves are stored in bulk,
+/// like move lists and hash tables. `Move::pack` and `PackedMove::unpack`
+/// convert between the two.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Default)]
+pub struct PackedMove(u16);
+
+impl PackedMove {
+    const QUIET: u16          = 0b0000;
+    const KING_CASTLE: u16    = 0b0010;
+    const QUEEN_CASTLE: u16   = 0b0011;
+    const CAPTURE: u16        = 0b0100;
+    const EN_PASSANT: u16     = 0b0101;
+    /// Set on promotions, with the piece in the lowest two bits
+    const PROMOTION: u16      = 0b1000;
+
+    /// A value that is not a move, a1 to a1
+    pub fn null() -> PackedMove {
+        PackedMove(0)
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// The raw 16 bits
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    pub fn from_bits(bits: u16) -> PackedMove {
+        PackedMove(bits)
+    }
+
+    pub fn from_sq(&self) -> Square {
+        Square::from_index((self.0 & 0x3f) as usize)
+    }
+
+    pub fn to_sq(&self) -> Square {
+        Square::from_index(((self.0 >> 6) & 0x3f) as usize)
+    }
+
+    fn flags(&self) -> u16 {
+        self.0 >> 12
+    }
+
+    pub fn is_capture(&self) -> bool {
+        self.flags() & PackedMove::CAPTURE != 0
+    }
+
+    pub fn is_en_passant(&self) -> bool {
+        self.flags() == PackedMove::EN_PASSANT
+    }
+
+    pub fn is_castling(&self) -> bool {
+        self.flags() == PackedMove::KING_CASTLE || self.flags() == PackedMove::QUEEN_CASTLE
+    }
+
+    /// Type of the piece promoted to
+    pub fn promotion(&self) -> Option<PieceType> {
+        if self.flags() & PackedMove::PROMOTION == 0 { return None; }
+        Some([Knight, Bishop, Rook, Queen][(self.flags() & 0b11) as usize])
+    }
+
+    /// Restore the full move in the position it was made in. Returns `None`
+    /// if it does not fit the position, for example because the piece on the
+    /// origin square is gone. The move is not checked for legality.
+    pub fn unpack(&self, pos: &Pos) -> Option<Move> {
+        if self.is_null() { return None; }
+        let from = self.from_sq();
+        let to = self.to_sq();
+        let piece = pos.board.get(from)?;
+        let Pc(color, pt) = piece;
+        if color != pos.turn { return None; }
+
+        let mut castling = None;
+        if self.is_castling() {
+            if pt != King { return None; }
+            let [kingside, queenside] = CastlingMove::for_color(color);
+            let cst = if self.flags() == PackedMove::KING_CASTLE { kingside } else { queenside };
+            if cst.king_target() != to { return None; }
+            castling = Some(cst);
+        }
+
+        let en_passant = self.is_en_passant();
+        let capture = if en_passant {
+            if pt != Pawn || pos.en_passant != Some(to) { return None; }
+            Some(Pc(color.other(), Pawn))
+        } else if self.is_capture() {
+            match pos.board.get(to) {
+                Some(Pc(c, t)) if c != color && t != King => Some(Pc(c, t)),
+                _ => { return None; }
+            }
+        } else if castling.is_none() && pos.board.get(to).is_some() {
+            return None;
+        } else {
+            None
+        };
+
+        let promotion = match self.promotion() {
+            None => None,
+            Some(_) if pt != Pawn => { return None; },
+            Some(p) => Some(Pc(color, p))
+        };
+
+        Some(Move {
+            from,
+            to,
+            piece,
+            capture,
+            promotion,
+            castling,
+            en_passant,
+        })
+    }
+}
+
+impl fmt::Display for PackedMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.from_sq(), self.to_sq())?;
+        if let Some(p) = self.promotion() {
+            write!(f, "{}", p.to_char())?;
+        }
+        Ok(())
+    }
+}
+
+impl Move {
+    /// Pack the move into 16 bits
+    pub fn pack(&self) -> PackedMove {
+        let mut flags = match self.castling {
+            Some(c) if c.is_kingside() => PackedMove::KING_CASTLE,
+            Some(_) => PackedMove::QUEEN_CASTLE,
+            None if self.en_passant => PackedMove::EN_PASSANT,
+            None if self.capture.is_some() => PackedMove::CAPTURE,
+            None => PackedMove::QUIET,
+        };
+        if let Some(Pc(_, p)) = self.promotion {
+            flags |= PackedMove::PROMOTION | match p {
+                Knight => 0,
+                Bishop => 1,
+                Rook   => 2,
+                _      => 3,
+            };
+        }
+        let from = self.from.index() as u16;
+        let to = self.to.index() as u16;
+        PackedMove(from | to << 6 | flags << 12)
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum CastlingMove {
     WhiteKingside   = 0b0001,
@@ -184,79 +434,108 @@ use self::CastlingMove::*;
 
 impl CastlingMove {
 
-    pub fn empty_pattern(&self) -> BitBoard {
+    /// All castling moves, in the order of their flags
+    pub fn all() -> [CastlingMove; 4] {
+        [WhiteKingside, WhiteQueenside, BlackKingside, BlackQueenside]
+    }
+
+    /// Castling moves available to the given player
+    pub fn for_color(c: Color) -> [CastlingMove; 2] {
+        match c {
+            White => [WhiteKingside, WhiteQueenside],
+            Black => [BlackKingside, BlackQueenside],
+        }
+    }
+
+    pub fn color(&self) -> Color {
         match *self {
-            WhiteKingside  => BitBoard::new(0x0000_0000_0000_0060),
-            WhiteQueenside => BitBoard::new(0x0000_0000_0000_000e),
-            BlackKingside  => BitBoard::new(0x6000_0000_0000_0000),
-            BlackQueenside => BitBoard::new(0x0e00_0000_0000_0000),
+            WhiteKingside | WhiteQueenside => White,
+            BlackKingside | BlackQueenside => Black,
         }
     }
 
-    pub fn get_rook_move(&self) -> (BitBoard, BitBoard) {
+    pub fn is_kingside(&self) -> bool {
+        *self == WhiteKingside || *self == BlackKingside
+    }
+
+    /// Index of the flag, from 0 to 3
+    pub fn index(&self) -> usize {
+        (*self as u8).trailing_zeros() as usize
+    }
+
+    /// Square the king ends up on, the g or c file. This is the same in Chess960.
+    pub fn king_target(&self) -> Square {
         match *self {
-            WhiteKingside  =>
-                (BitBoard::from_square(7), BitBoard::from_square(5)),
-            WhiteQueenside  =>
-                (BitBoard::from_square(0), BitBoard::from_square(3)),
-            BlackKingside  =>
-                (BitBoard::from_square(63), BitBoard::from_square(61)),
-            BlackQueenside  =>
-                (BitBoard::from_square(56), BitBoard::from_square(59)),
+            WhiteKingside  => Square::from_index(6),
+            WhiteQueenside => Square::from_index(2),
+            BlackKingside  => Square::from_index(62),
+            BlackQueenside => Square::from_index(58),
         }
     }
 
-    pub fn from_squares(sq: BitBoard) -> Option<CastlingMove> {
-        if sq == BitBoard::from_square(4) | BitBoard::from_square(6) {
-            return Some(WhiteKingside);
-        } else if sq == BitBoard::from_square(4) | BitBoard::from_square(2) {
-            return Some(WhiteQueenside);
-        } else if sq == BitBoard::from_square(60) | BitBoard::from_square(62) {
-            return Some(BlackQueenside);
-        } else if sq == BitBoard::from_square(60) | BitBoard::from_square(58) {
-            return Some(BlackQueenside);
-        } else {
-            return None;
+    /// Square the rook ends up on, the f or d file
+    pub fn rook_target(&self) -> Square {
+        match *self {
+            WhiteKingside  => Square::from_index(5),
+            WhiteQueenside => Square::from_index(3),
+            BlackKingside  => Square::from_index(61),
+            BlackQueenside => Square::from_index(59),
         }
     }
 
-    pub fn to_char(&self) -> char {
+    /// Rook origin in standard chess
+    pub fn standard_rook(&self) -> Square {
         match *self {
-            WhiteKingside  => 'K',
-            WhiteQueenside => 'Q',
-            BlackKingside  => 'k',
-            BlackQueenside => 'q',
+            WhiteKingside  => Square::from_index(7),
+            WhiteQueenside => Square::from_index(0),
+            BlackKingside  => Square::from_index(63),
+            BlackQueenside => Square::from_index(56),
         }
     }
 
-    pub fn from_char(c: char) -> Self {
-        match c {
-             'K' => WhiteKingside,
-             'Q' => WhiteQueenside,
-             'k' => BlackKingside,
-             'q' => BlackQueenside,
-             _   => panic!("invalid casling right!")
+    /// King origin in standard chess
+    pub fn standard_king(&self) -> Square {
+        match self.color() {
+            White => Square::from_index(4),
+            Black => Square::from_index(60),
         }
     }
 
-    pub fn str_to_flags(s: &str) -> u8 {
-        let mut flg = 0;
-        for c in s.chars() {
-            if c == '-' { return 0; }
+    /// Squares that must be empty for castling with the king and rook on
+    /// the given squares. The king and rook themselves are not included.
+    pub fn empty_pattern(&self, king: Square, rook: Square) -> BitBoard {
+        (king.bb().rank_span(self.king_target().bb()) | rook.bb().rank_span(self.rook_target().bb()))
+            & !king.bb() & !rook.bb()
+    }
+
+    /// Squares the king starts from, passes through and lands on.
+    /// None of them may be attacked when castling.
+    pub fn king_path(&self, king: Square) -> BitBoard {
+        king.bb().rank_span(self.king_target().bb())
+    }
+
+    /// Castling in standard chess notation, from the squares of the king move
+    pub fn from_squares(from: Square, to: Square) -> Option<CastlingMove> {
+        CastlingMove::all().iter().cloned()
+            .find(|c| from == c.standard_king() && to == c.king_target())
+    }
 
-            flg |= CastlingMove::from_char(c) as u8;
+    pub fn to_char(&self) -> char {
+        match *self {
+            WhiteKingside  => 'K',
+            WhiteQueenside => 'Q',
+            BlackKingside  => 'k',
+            BlackQueenside => 'q',
         }
-        flg
     }
 
-    pub fn flags_to_str(f: u8) -> String {
-        if f == 0 { return "-".to_string(); }
-        let mut s = String::new();
-        for v in [WhiteKingside, WhiteQueenside, BlackKingside, BlackQueenside].iter() {
-            if (*v as u8 & f) != 0 {
-                s.push(v.to_char());
-            }
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+             'K' => Some(WhiteKingside),
+             'Q' => Some(WhiteQueenside),
+             'k' => Some(BlackKingside),
+             'q' => Some(BlackQueenside),
+             _   => None
         }
-        s
     }
 }
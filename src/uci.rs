@@ -2,16 +2,115 @@
 
 extern crate rook;
 
+use rook::eval;
 use rook::pos::Pos;
-use rook::types::{Move};
+use rook::types::{Move, Outcome};
+use rook::tt::TranspositionTable;
+use rook::search;
+use rook::search::Limits;
+use rook::types::Color::*;
 
+use std::cmp;
 use std::fs::File;
+use std::time::{Duration, Instant};
 use std::io;
 use std::io::prelude::*;
 
+/// Reports the outcome to the GUI and returns true if there are no moves to search
+fn game_over(game: &Pos) -> bool {
+    match game.outcome() {
+        None => false,
+        Some(o) => {
+            println!("info string {}", o);
+            match o {
+                Outcome::Checkmate(_) | Outcome::Stalemate => true,
+                _ => false
+            }
+        }
+    }
+}
+
+/// Score for `info`, as `cp 35` or `mate 3`
+fn uci_score(score: i64) -> String {
+    match eval::mate_in_moves(score) {
+        Some(n) => format!("mate {}", n),
+        None => format!("cp {}", score),
+    }
+}
+
+/// Play the moves of a `position` command. Stops at the first one that is
+/// not legal, and returns false if there was one.
+fn play_moves<'a, I: Iterator<Item = &'a str>>(game: &mut Pos, moves: I) -> bool {
+    for m in moves {
+        match Move::from_str(game, m) {
+            Some(mv) => { game.make_move(mv); },
+            None => {
+                println!("info string illegal move {}", m);
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Moves of a principal variation in UCI notation, separated by spaces
+fn uci_pv(game: &Pos, pv: &[Move]) -> String {
+    let mut pos = game.duplicate();
+    let mut moves = Vec::new();
+    for &mv in pv {
+        moves.push(mv.to_uci(&pos));
+        pos.make_move(mv);
+    }
+    moves.join(" ")
+}
+
+/// Limits from the arguments of `go`. Without any, the search goes to
+/// `DEFAULT_DEPTH`.
+fn go_limits(game: &Pos, args: &[&str]) -> Limits {
+    let mut limits = Limits::default();
+    let mut left = None;
+    let mut increment = Duration::from_millis(0);
+    let mut moves_to_go = None;
+    for (i, &arg) in args.iter().enumerate() {
+        let value = args.get(i + 1).and_then(|v| v.parse::<u64>().ok());
+        let ms = value.map(Duration::from_millis);
+        match (arg, game.turn) {
+            ("depth", _) => { limits.depth = value.map(|d| d as usize); },
+            ("nodes", _) => { limits.nodes = value.map(|n| n as usize); },
+            ("movetime", _) => { limits.time = ms; },
+            ("wtime", White) | ("btime", Black) => { left = ms; },
+            ("winc", White) | ("binc", Black) => { increment = ms.unwrap_or(increment); },
+            ("movestogo", _) => { moves_to_go = value.map(|n| n as usize); },
+            _ => {}
+        }
+    }
+
+    if let Some(left) = left {
+        let time = Limits::time_for_move(left, increment, moves_to_go);
+        limits.time = Some(limits.time.map_or(time, |t| cmp::min(t, time)));
+    }
+    // A search can not be stopped while it runs, so `go infinite` gets the
+    // default depth too
+    if limits.depth.is_none() && limits.nodes.is_none() && limits.time.is_none() {
+        limits.depth = Some(DEFAULT_DEPTH);
+    }
+    limits
+}
+
+/// Depth searched when `go` gives no limits
+const DEFAULT_DEPTH: usize = 5;
+
+/// Transposition table size in MB, and the largest the GUI may ask for
+const HASH_DEFAULT_MB: usize = 16;
+const HASH_MAX_MB: usize = 4096;
+
 fn main() {
     let mut game = Pos::start();
     let mut log = File::create("log.txt").unwrap();
+    let mut chess960 = false;
+    // Cleared when the GUI sends a position we refuse to search
+    let mut valid = true;
+    let mut tt = TranspositionTable::new(HASH_DEFAULT_MB);
 
     loop {
         let stdin = io::stdin();
@@ -27,6 +126,10 @@ fn main() {
             if line == "uci" {
                 response.push_str("id name rook\n");
                 response.push_str("id author Alex\n");
+                response.push_str("option name UCI_Chess960 type check default false\n");
+                response.push_str(&format!("option name Hash type spin default {} min 1 max {}\n",
+                                           HASH_DEFAULT_MB, HASH_MAX_MB));
+                response.push_str("option name Clear Hash type button\n");
                 response.push_str("uciok");
             }
             else if line.starts_with("isready") {
@@ -34,54 +137,93 @@ fn main() {
             }
             else if line.starts_with("ucinewgame") {
                 game = Pos::empty();
+                tt.clear();
+            }
+            else if line.starts_with("setoption name UCI_Chess960 value") {
+                chess960 = args.last() == Some(&"true");
+            }
+            else if line.starts_with("setoption name Hash value") {
+                match args.last().and_then(|v| v.parse::<usize>().ok()) {
+                    Some(mb) => { tt.resize(mb.clamp(1, HASH_MAX_MB)); },
+                    None => { println!("info string invalid Hash size"); }
+                }
+            }
+            else if line == "setoption name Clear Hash" {
+                tt.clear();
+            }
+            else if line == "d" {
+                response.push_str(&format!("{}\nFen: {}", game, game.to_fen()));
             }
             else if line.starts_with("position fen") {
-                println!("parsing fen");
-                let fen_v = line.split(" ").skip(2).take(6).collect::<Vec<&str>>();
-                let fen_str = fen_v.join(" ");
-                println!("fen '{}'", fen_str);
-
-                game = Pos::from_fen(&fen_str);
-                println!("{}", game);
-                for mv in line.split(" ").skip(9) {
-                    let mv = Move::from_str(&game, mv).unwrap();
-                    game.make_move(mv);
+                let mut split = line.split_whitespace().skip(2);
+                let fen_str = split.by_ref()
+                    .take_while(|&t| t != "moves")
+                    .collect::<Vec<&str>>()
+                    .join(" ");
+
+                // A refused position is not kept, so `d` does not show the
+                // previous one
+                valid = false;
+                game = Pos::empty();
+                match Pos::from_fen(&fen_str) {
+                    Ok(p) => {
+                        let problems = p.validate();
+                        if !problems.is_empty() {
+                            for pr in problems { println!("info string illegal position: {}", pr); }
+                        } else {
+                            game = p;
+                            game.chess960 |= chess960;
+                            valid = play_moves(&mut game, split);
+                        }
+                    },
+                    Err(e) => { println!("info string {}", e); }
                 }
             }
             else if line.starts_with("position startpos") {
                 game = Pos::start();
+                game.chess960 = chess960;
+                valid = true;
                 let mut split = line.split(" ");
                 let _ = split.next();
                 let _ = split.next();
                 if Some("moves") == split.next() {
-                    for m in split {
-                        let mv = Move::from_str(&game, m).unwrap();
-                        game.make_move(mv);
-                    }
+                    valid = play_moves(&mut game, split);
                 }
             }
-            else if line.starts_with("go ") {
-                let mut depth = 5;
-                if args.len() > 1 && args[1] == "depth" {
-
-                    // log.write_all("got depth cmd".as_bytes());
-                    match args[2].parse::<usize>() {
-                        Ok(d) => {depth = d;},
-                        _ => {}
-                    }
-                }
-                for d in 1 .. depth {
-                    let (tmp_score, tmp_nodes, tmp_best) = game.negamax_start(d);
-                    if let Some(tbest) = tmp_best {
-                        println!("info depth {} nodes {} pv {} score cp {}", d, tmp_nodes, tbest.to_str(), tmp_score);
-                    }
+            else if line.starts_with("go perft") {
+                let depth = args.get(2).and_then(|d| d.parse::<usize>().ok()).unwrap_or(1);
+                let start = Instant::now();
+                let nodes = game.perft(depth);
+                let ms = start.elapsed().as_millis() as usize;
+                response.push_str(&format!("info depth {} nodes {} time {} nps {}",
+                                           depth, nodes, ms, nodes * 1000 / cmp::max(ms, 1)));
+            }
+            else if line.starts_with("go") && !valid {
+                response.push_str("bestmove 0000");
+            }
+            else if line.starts_with("go") && game_over(&game) {
+                response.push_str("bestmove 0000");
+            }
+            else if line.starts_with("go") {
+                let limits = go_limits(&game, &args[1..]);
+                let last = search::iterative_deepening(&game, limits, &mut tt, |it| {
+                    let ms = it.time.as_millis() as usize;
+                    println!("info depth {} score {} nodes {} time {} nps {} hashfull {} pv {}",
+                             it.depth, uci_score(it.score), it.nodes, ms,
+                             it.nodes * 1000 / cmp::max(ms, 1), it.hashfull, uci_pv(&game, &it.pv));
+                });
+                match last.and_then(|it| it.best_move()) {
+                    Some(mv) => { response.push_str(&format!("bestmove {}", mv.to_uci(&game))); },
+                    None => { response.push_str("bestmove 0000"); }
                 }
-                let (s, n, best_move) = game.negamax_start(depth);
-                if let Some(tbest) = best_move {
-                    println!("info depth {} nodes {} pv {} score cp {}", depth, n, tbest.to_str(), s);
+            }
+
+            if line.starts_with("position") {
+                if valid {
+                    log.write_all(format!("# {}\n", game.to_fen()).as_bytes());
+                } else {
+                    log.write_all("# position refused\n".as_bytes());
                 }
-                let res = format!("bestmove {}", best_move.unwrap().to_str());
-                response.push_str(&res);
             }
 
             if response != "" {